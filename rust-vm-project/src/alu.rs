@@ -0,0 +1,131 @@
+use crate::fault::VmFault;
+
+/**
+ * Condition flags, left behind by every ALU instruction and `cmp`.
+ *
+ * `zero` and `negative` (bit 63) describe the result. `carry` is the
+ * unsigned carry out, or the borrow for `sub`/`cmp`, or the last bit shifted
+ * out for `shl`/`shr`. `overflow` is set when the result taken as an i64
+ * overflowed. Since `add`, `sub` and `mul` fault on unsigned overflow,
+ * `carry` is only ever seen set after `cmp` or a shift.
+ */
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Flags {
+    pub zero: bool,
+    pub negative: bool,
+    pub carry: bool,
+    pub overflow: bool,
+}
+
+impl Flags {
+    fn new(value: u64, carry: bool, overflow: bool) -> Self {
+        Self {
+            zero: value == 0,
+            negative: (value as i64) < 0,
+            carry,
+            overflow,
+        }
+    }
+
+    /// Packs the flags into bits 0 to 3: zero, negative, carry, overflow
+    pub fn bits(&self) -> u64 {
+        self.zero as u64
+            | (self.negative as u64) << 1
+            | (self.carry as u64) << 2
+            | (self.overflow as u64) << 3
+    }
+
+    /// Inverse of `bits`, anything above bit 3 is ignored
+    pub fn from_bits(bits: u64) -> Self {
+        Self {
+            zero: bits & 1 != 0,
+            negative: bits & 2 != 0,
+            carry: bits & 4 != 0,
+            overflow: bits & 8 != 0,
+        }
+    }
+
+    /// Flags with no carry or overflow, for the bitwise instructions
+    fn logical(value: u64) -> Self {
+        Self::new(value, false, false)
+    }
+}
+
+pub(crate) type AluResult = Result<(u64, Flags), VmFault>;
+
+pub(crate) fn add(a: u64, b: u64) -> AluResult {
+    let value = a.checked_add(b).ok_or(VmFault::ArithmeticOverflow)?;
+    let overflow = (a as i64).overflowing_add(b as i64).1;
+    Ok((value, Flags::new(value, false, overflow)))
+}
+
+pub(crate) fn sub(a: u64, b: u64) -> AluResult {
+    let value = a.checked_sub(b).ok_or(VmFault::ArithmeticOverflow)?;
+    let overflow = (a as i64).overflowing_sub(b as i64).1;
+    Ok((value, Flags::new(value, false, overflow)))
+}
+
+pub(crate) fn mul(a: u64, b: u64) -> AluResult {
+    let value = a.checked_mul(b).ok_or(VmFault::ArithmeticOverflow)?;
+    let overflow = (a as i64).overflowing_mul(b as i64).1;
+    Ok((value, Flags::new(value, false, overflow)))
+}
+
+pub(crate) fn div(a: u64, b: u64) -> AluResult {
+    let value = a.checked_div(b).ok_or(VmFault::DivideByZero)?;
+    Ok((value, Flags::logical(value)))
+}
+
+pub(crate) fn rem(a: u64, b: u64) -> AluResult {
+    let value = a.checked_rem(b).ok_or(VmFault::DivideByZero)?;
+    Ok((value, Flags::logical(value)))
+}
+
+pub(crate) fn and(a: u64, b: u64) -> AluResult {
+    Ok((a & b, Flags::logical(a & b)))
+}
+
+pub(crate) fn or(a: u64, b: u64) -> AluResult {
+    Ok((a | b, Flags::logical(a | b)))
+}
+
+pub(crate) fn xor(a: u64, b: u64) -> AluResult {
+    Ok((a ^ b, Flags::logical(a ^ b)))
+}
+
+pub(crate) fn not(a: u64, _: u64) -> AluResult {
+    Ok((!a, Flags::logical(!a)))
+}
+
+/// Shifting by 64 or more leaves zero
+pub(crate) fn shl(a: u64, b: u64) -> AluResult {
+    let value = if b < 64 { a << b } else { 0 };
+    let carry = (1..=64).contains(&b) && (a >> (64 - b)) & 1 == 1;
+    Ok((value, Flags::new(value, carry, false)))
+}
+
+/// Logical, shifting by 64 or more leaves zero
+pub(crate) fn shr(a: u64, b: u64) -> AluResult {
+    let value = if b < 64 { a >> b } else { 0 };
+    let carry = (1..=64).contains(&b) && (a >> (b - 1)) & 1 == 1;
+    Ok((value, Flags::new(value, carry, false)))
+}
+
+/// The rotate amount is taken mod 64
+pub(crate) fn rol(a: u64, b: u64) -> AluResult {
+    let value = a.rotate_left((b % 64) as u32);
+    Ok((value, Flags::logical(value)))
+}
+
+/// The rotate amount is taken mod 64
+pub(crate) fn ror(a: u64, b: u64) -> AluResult {
+    let value = a.rotate_right((b % 64) as u32);
+    Ok((value, Flags::logical(value)))
+}
+
+/// `cmp` works out a - b without faulting and keeps only the flags
+pub(crate) fn compare(a: u64, b: u64) -> Flags {
+    let (value, borrow) = a.overflowing_sub(b);
+    let overflow = (a as i64).overflowing_sub(b as i64).1;
+    Flags::new(value, borrow, overflow)
+}
@@ -0,0 +1,295 @@
+use crate::image::{ProgramImage, Section, Symbol};
+use crate::{incode_instr, Instruction, Operand};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+/**
+ * A two pass assembler for the mnemonics listed on `Instruction`.
+ *
+ * Source is line oriented, `;` starts a comment:
+ *
+ * ```text
+ *         .entry start
+ * start:  lod one r1      ; labels can share a line with an instruction
+ *         li r2 0x10
+ *         spc start
+ *         .data
+ * one:    .word 1
+ * ```
+ *
+ * Registers are written `r0`..`r7` (a bare number also works), addresses,
+ * program counter targets and immediates take either a number or a label.
+ * Numbers can be decimal or `0x` hex, `lis` immediates and `.word`s can be
+ * negative. Every instruction and every `.word` takes exactly one
+ * u64. Words land in the code section unless they follow a `.data`
+ * directive (`.code` switches back), and the data section is laid out right
+ * after the code, so label `n` simply refers to the n-th word of the output,
+ * which is what `MemoryController::load_program_external` expects when
+ * loaded at 0.
+ */
+pub fn assemble(src: &str) -> Result<Vec<u64>> {
+    let image = assemble_image(src)?;
+    let mut out = image.code.words;
+    out.extend(image.data.words);
+    Ok(out)
+}
+
+/**
+ * Same as `assemble` but keeps the sections apart and records every label in
+ * the symbol table. The entry point is `.entry <label|address>` if given,
+ * otherwise the `start` label, otherwise the first code word. A `vectors`
+ * label marks the interrupt vector table when the image is loaded.
+ */
+pub fn assemble_image(src: &str) -> Result<ProgramImage> {
+    let lines = parse_lines(src)?;
+
+    let code_len = lines
+        .iter()
+        .filter(|line| line.emits() && line.section == SectionKind::Code)
+        .count() as u32;
+
+    // first pass, figure out where every label lands
+    let mut labels: HashMap<&str, u32> = HashMap::new();
+    let mut next = [0_u32, code_len];
+    for line in &lines {
+        let addr = &mut next[line.section as usize];
+        for label in &line.labels {
+            if labels.insert(label, *addr).is_some() {
+                return Err(anyhow!(
+                    "line {}: label `{}` defined twice",
+                    line.number,
+                    label
+                ));
+            }
+        }
+        if line.emits() {
+            *addr += 1;
+        }
+    }
+
+    // second pass, emit words
+    let mut code = Section::default();
+    let mut data = Section {
+        base: code_len,
+        words: Vec::new(),
+    };
+    let mut entry = None;
+    for line in &lines {
+        let err = |e: anyhow::Error| anyhow!("line {}: {}", line.number, e);
+        match &line.body {
+            Some(Body::Entry(arg)) => entry = Some(parse_u32(arg, &labels).map_err(err)?),
+            Some(body) => {
+                let word = encode(body, &labels).map_err(err)?;
+                match line.section {
+                    SectionKind::Code => code.words.push(word),
+                    SectionKind::Data => data.words.push(word),
+                }
+            }
+            None => {}
+        }
+    }
+
+    let entry = entry.or_else(|| labels.get("start").copied()).unwrap_or(0);
+    let mut image = ProgramImage::new(entry, code, data);
+    image.symbols = labels
+        .iter()
+        .map(|(name, addr)| Symbol {
+            name: name.to_string(),
+            addr: *addr,
+        })
+        .collect();
+    image
+        .symbols
+        .sort_by(|a, b| (a.addr, &a.name).cmp(&(b.addr, &b.name)));
+
+    Ok(image)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum SectionKind {
+    Code = 0,
+    Data = 1,
+}
+
+struct Line<'s> {
+    number: usize,
+    section: SectionKind,
+    labels: Vec<&'s str>,
+    body: Option<Body<'s>>,
+}
+
+impl Line<'_> {
+    /// Whether this line takes up a word of output
+    fn emits(&self) -> bool {
+        matches!(self.body, Some(Body::Word(_)) | Some(Body::Instr(..)))
+    }
+}
+
+enum Body<'s> {
+    Word(&'s str),
+    Instr(&'s str, Vec<&'s str>),
+    Entry(&'s str),
+}
+
+fn parse_lines(src: &str) -> Result<Vec<Line<'_>>> {
+    let mut section = SectionKind::Code;
+    let mut lines = Vec::new();
+    for (idx, text) in src.lines().enumerate() {
+        let number = idx + 1;
+        let mut rest = text.split(';').next().unwrap_or("").trim();
+        let mut labels = Vec::new();
+
+        while let Some(colon) = rest.find(':') {
+            let label = rest[..colon].trim();
+            if !is_identifier(label) {
+                return Err(anyhow!("line {}: bad label `{}`", number, label));
+            }
+            labels.push(label);
+            rest = rest[colon + 1..].trim();
+        }
+
+        let mut tokens = rest
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|t| !t.is_empty());
+
+        let body = match tokens.next() {
+            None => None,
+            Some(directive @ (".word" | ".entry")) => {
+                let value = tokens
+                    .next()
+                    .ok_or_else(|| anyhow!("line {}: `{}` needs a value", number, directive))?;
+                if tokens.next().is_some() {
+                    return Err(anyhow!(
+                        "line {}: `{}` takes a single value",
+                        number,
+                        directive
+                    ));
+                }
+                if directive == ".word" {
+                    Some(Body::Word(value))
+                } else {
+                    Some(Body::Entry(value))
+                }
+            }
+            Some(".code") => {
+                section = SectionKind::Code;
+                None
+            }
+            Some(".data") => {
+                section = SectionKind::Data;
+                None
+            }
+            Some(directive) if directive.starts_with('.') => {
+                return Err(anyhow!(
+                    "line {}: unknown directive `{}`",
+                    number,
+                    directive
+                ));
+            }
+            Some(mnemonic) => Some(Body::Instr(mnemonic, tokens.collect())),
+        };
+
+        lines.push(Line {
+            number,
+            section,
+            labels,
+            body,
+        });
+    }
+    Ok(lines)
+}
+
+fn encode(body: &Body, labels: &HashMap<&str, u32>) -> Result<u64> {
+    match body {
+        Body::Word(value) => parse_word(value, labels),
+        Body::Entry(_) => unreachable!("directives don't emit words"),
+        Body::Instr(mnemonic, args) => {
+            let instr = Instruction::from_mnemonic(mnemonic)
+                .ok_or_else(|| anyhow!("unknown mnemonic `{}`", mnemonic))?;
+            let operands = instr.operands();
+            if args.len() != operands.len() {
+                return Err(anyhow!(
+                    "`{}` takes {} operand(s), {} given",
+                    mnemonic,
+                    operands.len(),
+                    args.len()
+                ));
+            }
+
+            let mut bytes = [0_u8; 8];
+            bytes[0] = instr as u8;
+            for (operand, arg) in operands.iter().zip(args) {
+                match *operand {
+                    Operand::Reg(idx) => bytes[idx] = parse_register(arg)?,
+                    Operand::Addr(idx) | Operand::Target(idx) | Operand::Imm(idx) => {
+                        let val = parse_u32(arg, labels)?;
+                        bytes[idx..idx + 4].copy_from_slice(&val.to_le_bytes());
+                    }
+                    Operand::SignedImm(idx) => {
+                        let val = parse_i32(arg, labels)?;
+                        bytes[idx..idx + 4].copy_from_slice(&val.to_le_bytes());
+                    }
+                }
+            }
+            Ok(incode_instr(bytes))
+        }
+    }
+}
+
+fn parse_register(arg: &str) -> Result<u8> {
+    let digits = arg.strip_prefix('r').unwrap_or(arg);
+    match digits.parse::<u8>() {
+        Ok(reg) if reg < 8 => Ok(reg),
+        _ => Err(anyhow!("bad register `{}`, expected r0..r7", arg)),
+    }
+}
+
+fn parse_u32(arg: &str, labels: &HashMap<&str, u32>) -> Result<u32> {
+    if let Some(addr) = labels.get(arg) {
+        return Ok(*addr);
+    }
+    let val = parse_number(arg)?;
+    u32::try_from(val).map_err(|_| anyhow!("`{}` does not fit in a u32", arg))
+}
+
+fn parse_i32(arg: &str, labels: &HashMap<&str, u32>) -> Result<i32> {
+    let val = parse_word(arg, labels)?;
+    // negatives come back from `parse_word` as two's complement
+    let val = match arg.starts_with('-') {
+        true => val as i64,
+        false => i64::try_from(val).unwrap_or(i64::MAX),
+    };
+    i32::try_from(val).map_err(|_| anyhow!("`{}` does not fit in an i32", arg))
+}
+
+fn parse_word(arg: &str, labels: &HashMap<&str, u32>) -> Result<u64> {
+    if let Some(addr) = labels.get(arg) {
+        return Ok(*addr as u64);
+    }
+    if let Some(neg) = arg.strip_prefix('-') {
+        let val = parse_number(neg)?;
+        if val > 1 << 63 {
+            return Err(anyhow!("`{}` does not fit in an i64", arg));
+        }
+        return Ok((val as i64).wrapping_neg() as u64);
+    }
+    parse_number(arg)
+}
+
+pub(crate) fn parse_number(arg: &str) -> Result<u64> {
+    let parsed = if let Some(hex) = arg.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16)
+    } else {
+        arg.parse::<u64>()
+    };
+    parsed.map_err(|_| anyhow!("expected a number or label, got `{}`", arg))
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -0,0 +1,301 @@
+use crate::decode::Op;
+use crate::disasm;
+use crate::image::{ProgramImage, Symbol};
+use crate::interrupt::VECTORS;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+/// How control gets from the end of one block to the start of another
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EdgeKind {
+    FallThrough, // on to the next word, a branch not taken or back from a call
+    Jump,        // spc
+    Branch,      // ieqe or a conditional branch, taken
+    Call,        // into the callee
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Edge {
+    pub to: u32, // start of the block control goes to
+    pub kind: EdgeKind,
+}
+
+/// A straight run of code words, only ever entered at `start`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub start: u32,
+    pub words: Vec<u64>,
+    pub edges: Vec<Edge>,
+}
+
+impl Block {
+    /// One past the last word
+    pub fn end(&self) -> u64 {
+        self.start as u64 + self.words.len() as u64
+    }
+}
+
+/// A block some edge loops back to, the blocks those edges come from and
+/// every block on the way round, header included
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    pub header: u32,
+    pub latches: Vec<u32>,
+    pub body: Vec<u32>,
+}
+
+/**
+ * The control-flow graph of an image's code section. Blocks start at the
+ * entry point, every `vectors` handler, every jump, branch and call target
+ * and right after any word that can send control elsewhere. A call gets an
+ * edge into the callee and a fall through edge to the word after it, `ret`
+ * and `iret` get none, and neither do `ext` and words that don't decode.
+ *
+ * Only targets written into the instruction words are followed, and ones
+ * outside the code section are dropped, `verify` reports those.
+ */
+#[derive(Debug, Clone)]
+pub struct Cfg {
+    blocks: BTreeMap<u32, Block>,
+    roots: Vec<u32>, // the entry point and the vector handlers
+}
+
+impl Cfg {
+    pub fn build(image: &ProgramImage) -> Self {
+        let code = &image.code;
+        // words past the end of the address space can't be reached, leave them out
+        let words = &code.words[..code.words.len().min((1 << 32) - code.base as usize)];
+        let code_end = code.base as u64 + words.len() as u64;
+        let in_code = |addr: u64| {
+            u32::try_from(addr)
+                .ok()
+                .filter(|_| (code.base as u64..code_end).contains(&addr))
+        };
+
+        let mut roots: Vec<u32> = in_code(image.entry as u64).into_iter().collect();
+        if let Some(base) = image.symbol("vectors") {
+            for slot in 0..VECTORS {
+                let Some(addr) = base.checked_add(slot) else {
+                    break;
+                };
+                let handler = image.data.word(addr).or_else(|| code.word(addr));
+                if let Some(handler) = handler.filter(|&h| h != 0) {
+                    roots.extend(in_code(handler));
+                }
+            }
+        }
+
+        let mut leaders: BTreeSet<u32> = roots.iter().copied().collect();
+        leaders.extend(in_code(code.base as u64));
+        for (i, &raw) in words.iter().enumerate() {
+            let addr = code.base as u64 + i as u64;
+            if let Some(exits) = exits(addr, raw) {
+                leaders.extend(in_code(addr + 1));
+                leaders.extend(exits.iter().filter_map(|&(to, _)| in_code(to)));
+            }
+        }
+
+        let starts: Vec<u32> = leaders.into_iter().collect();
+        let mut blocks = BTreeMap::new();
+        for (i, &start) in starts.iter().enumerate() {
+            let end = starts.get(i + 1).map_or(code_end, |&next| next as u64);
+            let words =
+                words[(start - code.base) as usize..(end - code.base as u64) as usize].to_vec();
+            let edges = exits(end - 1, words[words.len() - 1])
+                .unwrap_or_else(|| vec![(end, EdgeKind::FallThrough)])
+                .into_iter()
+                .filter_map(|(to, kind)| {
+                    Some(Edge {
+                        to: in_code(to)?,
+                        kind,
+                    })
+                })
+                .collect();
+            blocks.insert(
+                start,
+                Block {
+                    start,
+                    words,
+                    edges,
+                },
+            );
+        }
+
+        roots.sort_unstable();
+        roots.dedup();
+        Self { blocks, roots }
+    }
+
+    /// Every block in address order
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.values()
+    }
+
+    /// The block holding `addr`, if it's code
+    pub fn block(&self, addr: u32) -> Option<&Block> {
+        let (_, block) = self.blocks.range(..=addr).next_back()?;
+        Some(block).filter(|block| (addr as u64) < block.end())
+    }
+
+    /// Where execution can start, the entry point and any vector handlers
+    pub fn roots(&self) -> &[u32] {
+        &self.roots
+    }
+
+    /// Starts of the blocks nothing reaches from the roots
+    pub fn unreachable(&self) -> Vec<u32> {
+        let reachable = self.reachable();
+        self.blocks
+            .keys()
+            .copied()
+            .filter(|start| !reachable.contains(start))
+            .collect()
+    }
+
+    /**
+     * Loops found walking depth first from the roots, one per header. An
+     * edge to a block still being walked is a back edge, and the body is
+     * everything reachable that gets to one of its latches without passing
+     * through the header.
+     */
+    pub fn loops(&self) -> Vec<Loop> {
+        let mut latches: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+        let mut visited = BTreeSet::new();
+        for &root in &self.roots {
+            if !visited.insert(root) {
+                continue;
+            }
+            let mut stack = vec![(root, 0)]; // block, next edge to follow
+            let mut walking = BTreeSet::from([root]);
+            while let Some(&(block, next)) = stack.last() {
+                let Some(edge) = self.blocks[&block].edges.get(next) else {
+                    walking.remove(&block);
+                    stack.pop();
+                    continue;
+                };
+                let top = stack.len() - 1;
+                stack[top].1 += 1;
+                if walking.contains(&edge.to) {
+                    latches.entry(edge.to).or_default().push(block);
+                } else if visited.insert(edge.to) {
+                    walking.insert(edge.to);
+                    stack.push((edge.to, 0));
+                }
+            }
+        }
+
+        let mut predecessors: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+        for block in self.blocks().filter(|block| visited.contains(&block.start)) {
+            for edge in &block.edges {
+                predecessors.entry(edge.to).or_default().push(block.start);
+            }
+        }
+
+        latches
+            .into_iter()
+            .map(|(header, mut latches)| {
+                latches.sort_unstable();
+                latches.dedup();
+                let mut body = BTreeSet::from([header]);
+                let mut work = latches.clone();
+                while let Some(block) = work.pop() {
+                    if body.insert(block) {
+                        work.extend(predecessors.get(&block).into_iter().flatten());
+                    }
+                }
+                Loop {
+                    header,
+                    latches,
+                    body: body.into_iter().collect(),
+                }
+            })
+            .collect()
+    }
+
+    /**
+     * The graph in Graphviz DOT, one box per block listing its disassembly
+     * under any labels from `symbols`. Unreachable blocks are greyed out,
+     * taken branches and calls are labelled, and back edges are drawn bold.
+     */
+    pub fn to_dot(&self, symbols: &[Symbol]) -> String {
+        let unreachable = self.unreachable();
+        let back_edges: BTreeSet<(u32, u32)> = self
+            .loops()
+            .iter()
+            .flat_map(|l| l.latches.iter().map(move |&latch| (latch, l.header)))
+            .collect();
+
+        let mut out = String::new();
+        let _ = writeln!(out, "digraph cfg {{");
+        let _ = writeln!(out, "    node [shape=box, fontname=\"monospace\"];");
+        for block in self.blocks() {
+            let mut label = String::new();
+            for line in disasm::disassemble(&block.words, block.start) {
+                for symbol in symbols.iter().filter(|s| s.addr == line.addr) {
+                    label.push_str(&format!("{}:\\l", escape(&symbol.name)));
+                }
+                label.push_str(&format!("{:>6}: {}\\l", line.addr, escape(&line.text)));
+            }
+            let style = if unreachable.contains(&block.start) {
+                ", color=gray, fontcolor=gray"
+            } else {
+                ""
+            };
+            let _ = writeln!(out, "    b{} [label=\"{}\"{}];", block.start, label, style);
+        }
+        for block in self.blocks() {
+            for edge in &block.edges {
+                let mut attrs = match edge.kind {
+                    EdgeKind::FallThrough | EdgeKind::Jump => vec![],
+                    EdgeKind::Branch => vec!["label=\"taken\""],
+                    EdgeKind::Call => vec!["label=\"call\"", "style=dashed"],
+                };
+                if back_edges.contains(&(block.start, edge.to)) {
+                    attrs.push("penwidth=2");
+                }
+                let attrs = if attrs.is_empty() {
+                    String::new()
+                } else {
+                    format!(" [{}]", attrs.join(", "))
+                };
+                let _ = writeln!(out, "    b{} -> b{}{};", block.start, edge.to, attrs);
+            }
+        }
+        let _ = writeln!(out, "}}");
+        out
+    }
+
+    fn reachable(&self) -> BTreeSet<u32> {
+        let mut seen = BTreeSet::new();
+        let mut work = self.roots.clone();
+        while let Some(start) = work.pop() {
+            if seen.insert(start) {
+                work.extend(self.blocks[&start].edges.iter().map(|edge| edge.to));
+            }
+        }
+        seen
+    }
+}
+
+/// Where the word at `addr` can send control, `None` if it just carries on
+/// to the next one
+fn exits(addr: u64, raw: u64) -> Option<Vec<(u64, EdgeKind)>> {
+    let next = addr + 1;
+    Some(match Op::decode(raw) {
+        Op::Jump(target) => vec![(target as u64, EdgeKind::Jump)],
+        Op::IfEq { target, .. } | Op::Branch { target, .. } => vec![
+            (target as u64, EdgeKind::Branch),
+            (next, EdgeKind::FallThrough),
+        ],
+        Op::Call(target) => vec![
+            (target as u64, EdgeKind::Call),
+            (next, EdgeKind::FallThrough),
+        ],
+        Op::Exit | Op::Return | Op::InterruptReturn | Op::Invalid(_) => vec![],
+        _ => return None,
+    })
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
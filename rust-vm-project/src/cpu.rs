@@ -0,0 +1,813 @@
+use crate::alu::{self, Flags};
+use crate::decode::{AluOp, Op};
+use crate::device::{attach_console, Device};
+use crate::disasm;
+use crate::fault::{Fault, VmFault};
+use crate::fuel::{CostTable, RunOutcome};
+use crate::host::HostFn;
+use crate::interrupt::{InterruptController, IRQ_VECTOR, VECTORS};
+use crate::memory::{FlatMemory, MemoryBackend, MemoryController, Permissions};
+use std::collections::HashMap;
+
+#[allow(clippy::upper_case_acronyms)]
+pub struct CPU<M: MemoryBackend = FlatMemory> {
+    memory_controller: MemoryController<M>,
+    reg_array: [u64; 8],
+    current_instruction: [u8; 8],
+    program_counter: u32,
+    flags: Flags,
+    stack_pointer: u32, // the stack grows down, this is the last word pushed
+    stack_base: u32,    // lowest address the stack may use
+    stack_top: u32,     // one past the highest, the stack is empty when sp == top
+    interrupts: InterruptController,
+    costs: CostTable,
+    cycles: u64, // cost of everything executed so far, see `CostTable`
+    host_calls: HashMap<u32, HostFn<M>>,
+    registers_written: u8, // bit n is set when rn is written, see `take_registers_written`
+}
+
+/// Bit of the status word pushed on interrupt entry that holds the enable
+const STATUS_INTERRUPTS: u64 = 1 << 4;
+
+/// The top of memory, a quarter of it at most. With the full 2^32 words
+/// the very last one is left out so the top still fits in a u32.
+fn default_stack(words: u64) -> (u32, u32) {
+    let top = words.min(u32::MAX as u64) as u32;
+    (top - (words / 4).min(64) as u32, top)
+}
+
+impl<M: MemoryBackend> CPU<M> {
+    pub fn print_state(&self) {
+        println!("Registers: {:?}", &self.reg_array);
+        println!("Current I: {:?}", &self.current_instruction);
+        println!("Program C: {}", &self.program_counter);
+        println!("Flags    : {:?}", &self.flags);
+        println!("Cycles   : {}", &self.cycles);
+        println!("Interrupt: {:?}", &self.interrupts);
+        println!(
+            "Stack  P: {} in {:?}",
+            &self.stack_pointer,
+            self.stack_region()
+        );
+        println!("Memory  H: {:?}", &self.mem_header());
+    }
+
+    pub fn mem_header(&self) -> String {
+        let words: Vec<u64> = (0..self.memory().words().min(30) as u32)
+            .map(|idx| self.memory().read(idx).unwrap_or(0))
+            .collect();
+        format!("{:?}", words)
+    }
+
+    pub fn new(mc: MemoryController<M>) -> Self {
+        let (stack_base, stack_top) = default_stack(mc.memory().words());
+        Self {
+            memory_controller: mc,
+            reg_array: [0_u64; 8],
+            current_instruction: [0_u8; 8],
+            program_counter: 0_u32,
+            flags: Flags::default(),
+            stack_pointer: stack_top,
+            stack_base,
+            stack_top,
+            interrupts: InterruptController::new(),
+            costs: CostTable::default(),
+            cycles: 0,
+            host_calls: HashMap::new(),
+            registers_written: 0,
+        }
+    }
+
+    pub fn builder(memory: M) -> MachineBuilder<M> {
+        MachineBuilder::new(memory)
+    }
+
+    pub fn registers(&self) -> &[u64; 8] {
+        &self.reg_array
+    }
+
+    pub fn register(&self, idx: u8) -> Result<u64, VmFault> {
+        self.read_from_reg(idx)
+    }
+
+    pub fn set_register(&mut self, idx: u8, val: u64) -> Result<(), VmFault> {
+        self.write_to_reg(idx, val)
+    }
+
+    pub fn program_counter(&self) -> u32 {
+        self.program_counter
+    }
+
+    pub fn set_program_counter(&mut self, val: u32) {
+        self.write_to_program_counter(val);
+    }
+
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
+
+    pub fn set_flags(&mut self, flags: Flags) {
+        self.flags = flags;
+    }
+
+    pub fn stack_pointer(&self) -> u32 {
+        self.stack_pointer
+    }
+
+    pub fn set_stack_pointer(&mut self, val: u32) {
+        self.stack_pointer = val;
+    }
+
+    /// The addresses the stack may use, `base..top`
+    pub fn stack_region(&self) -> std::ops::Range<u32> {
+        self.stack_base..self.stack_top
+    }
+
+    /// Moves the stack to `base..top` and empties it
+    pub fn set_stack_region(&mut self, region: std::ops::Range<u32>) {
+        self.stack_base = region.start;
+        self.stack_top = region.end;
+        self.stack_pointer = region.end;
+    }
+
+    pub fn interrupts(&self) -> &InterruptController {
+        &self.interrupts
+    }
+
+    /// Hosts can raise IRQ lines or move the vector table through this
+    pub fn interrupts_mut(&mut self) -> &mut InterruptController {
+        &mut self.interrupts
+    }
+
+    pub fn costs(&self) -> &CostTable {
+        &self.costs
+    }
+
+    pub fn set_costs(&mut self, costs: CostTable) {
+        self.costs = costs;
+    }
+
+    /// Total cost of the instructions executed so far, faulting ones included
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn current_instruction(&self) -> [u8; 8] {
+        self.current_instruction
+    }
+
+    pub(crate) fn set_current_instruction(&mut self, instruction: [u8; 8]) {
+        self.current_instruction = instruction;
+    }
+
+    pub(crate) fn set_cycles(&mut self, cycles: u64) {
+        self.cycles = cycles;
+    }
+
+    pub(crate) fn host_calls(&self) -> &HashMap<u32, HostFn<M>> {
+        &self.host_calls
+    }
+
+    pub(crate) fn host_calls_mut(&mut self) -> &mut HashMap<u32, HostFn<M>> {
+        &mut self.host_calls
+    }
+
+    /// Registers written since the last call as a bit mask, including ones
+    /// that were given the value they already had
+    pub(crate) fn take_registers_written(&mut self) -> u8 {
+        std::mem::take(&mut self.registers_written)
+    }
+
+    pub fn memory_controller(&self) -> &MemoryController<M> {
+        &self.memory_controller
+    }
+
+    pub fn memory_controller_mut(&mut self) -> &mut MemoryController<M> {
+        &mut self.memory_controller
+    }
+
+    pub fn memory(&self) -> &M {
+        self.memory_controller.memory()
+    }
+
+    pub fn memory_mut(&mut self) -> &mut M {
+        self.memory_controller.memory_mut()
+    }
+
+    /// Executes a single instruction, `Ok(false)` once the machine has exited
+    pub fn step(&mut self) -> Result<bool, Fault> {
+        self.cycle()
+    }
+
+    pub fn run(&mut self) -> Result<(), Fault> {
+        let mut last = true;
+
+        while last {
+            last = self.cycle()?;
+        }
+        Ok(())
+    }
+
+    /**
+     * Like `run` but stops once the executed instructions have cost
+     * `max_cycles` between them. An instruction is never cut short, so the
+     * last one can take the total past the budget, `OutOfFuel` reports what
+     * was actually used. Calling it again carries on where it stopped.
+     */
+    pub fn run_with_budget(&mut self, max_cycles: u64) -> Result<RunOutcome, Fault> {
+        let start = self.cycles;
+        loop {
+            let used = self.cycles - start;
+            if used >= max_cycles {
+                return Ok(RunOutcome::OutOfFuel { cycles: used });
+            }
+            if !self.cycle()? {
+                return Ok(RunOutcome::Exited {
+                    cycles: self.cycles - start,
+                });
+            }
+        }
+    }
+
+    pub fn run_debug(&mut self) -> Result<(), Fault> {
+        let mut last = true;
+
+        while last {
+            last = self.cycle_debug()?;
+        }
+        Ok(())
+    }
+
+    fn cycle(&mut self) -> Result<bool, Fault> {
+        self.poll_interrupts();
+        self.fetch_execute()
+    }
+
+    /// The rest of a cycle once interrupts have had their chance
+    pub(crate) fn fetch_execute(&mut self) -> Result<bool, Fault> {
+        // load
+        let op = match self.load_instruction() {
+            Ok(op) => op,
+            Err(kind) => return self.trap(kind),
+        };
+
+        self.execute_loaded(op)
+    }
+
+    /// Exit code from a device such as the halt port, `None` after plain `ext`
+    pub fn exit_code(&self) -> Option<u64> {
+        self.memory_controller.exit_code()
+    }
+
+    fn cycle_debug(&mut self) -> Result<bool, Fault> {
+        self.poll_interrupts();
+
+        // load
+        let op = match self.load_instruction() {
+            Ok(op) => op,
+            Err(kind) => return self.trap(kind),
+        };
+
+        println!("{:?}", &self.reg_array);
+        println!(
+            "PC: {}\nCI: {}\n",
+            self.program_counter,
+            disasm::disassemble_word(u64::from_le_bytes(self.current_instruction))
+                .unwrap_or_else(|| format!("{:?}", self.current_instruction))
+        );
+
+        self.execute_loaded(op)
+    }
+
+    /// Runs whatever `load_instruction` fetched. The program counter moves on
+    /// first, so jumps just overwrite it (even a jump to itself) and `call`
+    /// can push it as the return address. A fault puts it back.
+    fn execute_loaded(&mut self, op: Op) -> Result<bool, Fault> {
+        let tmp_pc = self.program_counter;
+        self.incr();
+        self.cycles = self
+            .cycles
+            .saturating_add(self.costs.opcode_cost(self.current_instruction[0]));
+
+        // execute
+        let out = match self.execute(op) {
+            Ok(out) => out,
+            Err(kind) => {
+                self.program_counter = tmp_pc;
+                return self.trap(kind);
+            }
+        };
+
+        // a device asking to halt stops the machine just like `ext`
+        Ok(out && self.memory_controller.exit_code().is_none())
+    }
+
+    /// Ticks the devices and enters the handler for the lowest pending IRQ
+    /// if interrupts are on. A handler that can't be entered because the
+    /// stack is full leaves the line dropped and the program carries on.
+    pub(crate) fn poll_interrupts(&mut self) {
+        self.interrupts.raise_mask(self.memory_controller.tick());
+        if let Some(line) = self.interrupts.take() {
+            if let Some(handler) = self.handler(IRQ_VECTOR + line as u32) {
+                let _ = self.enter_handler(self.program_counter, handler);
+            }
+        }
+    }
+
+    /// Sends a fault at the current program counter to its handler, or to
+    /// the host if it has none or the handler can't be entered
+    fn trap(&mut self, kind: VmFault) -> Result<bool, Fault> {
+        let fault = self.fault(kind);
+        let resume = self.program_counter.wrapping_add(1);
+        match self.handler(kind.vector()) {
+            Some(handler) if self.enter_handler(resume, handler).is_ok() => Ok(true),
+            _ => Err(fault),
+        }
+    }
+
+    /// The handler address in vector table slot `vector`, `None` if it's zero
+    fn handler(&self, vector: u32) -> Option<u32> {
+        let slot = self.interrupts.vectors()?.checked_add(vector)?;
+        let handler = self.memory_controller.peek(slot).ok()?;
+        (handler != 0).then_some(handler as u32)
+    }
+
+    /// Pushes `resume` and the status word, then jumps to `handler` with
+    /// interrupts off. Leaves the stack alone if either push fails.
+    fn enter_handler(&mut self, resume: u32, handler: u32) -> Result<(), VmFault> {
+        let sp = self.stack_pointer;
+        let mut status = self.flags.bits();
+        if self.interrupts.enabled() {
+            status |= STATUS_INTERRUPTS;
+        }
+        if let Err(kind) = self.push(resume as u64).and_then(|_| self.push(status)) {
+            self.stack_pointer = sp;
+            return Err(kind);
+        }
+        self.interrupts.set_enabled(false);
+        self.write_to_program_counter(handler);
+        Ok(())
+    }
+
+    /// Pins a fault on the instruction currently being executed
+    fn fault(&self, kind: VmFault) -> Fault {
+        Fault {
+            kind,
+            pc: self.program_counter,
+            instruction: self.current_instruction,
+        }
+    }
+
+    /// Fetches the instruction at the program counter, decoded, leaving its
+    /// word in `current_instruction`
+    fn load_instruction(&mut self) -> Result<Op, VmFault> {
+        self.current_instruction = [0_u8; 8];
+        let (raw, op) = self.memory_controller.fetch_decoded(self.program_counter)?;
+        self.current_instruction = raw.to_le_bytes();
+        Ok(op)
+    }
+
+    fn read_from_reg(&self, idx: u8) -> Result<u64, VmFault> {
+        self.reg_array
+            .get(idx as usize)
+            .copied()
+            .ok_or(VmFault::BadRegister(idx))
+    }
+
+    fn write_to_reg(&mut self, idx: u8, val: u64) -> Result<(), VmFault> {
+        let reg = self
+            .reg_array
+            .get_mut(idx as usize)
+            .ok_or(VmFault::BadRegister(idx))?;
+        *reg = val;
+        self.registers_written |= 1 << idx;
+        Ok(())
+    }
+
+    fn write_to_program_counter(&mut self, val: u32) {
+        self.program_counter = val;
+    }
+
+    fn execute(&mut self, op: Op) -> Result<bool, VmFault> {
+        // this is gonna be the biggie
+
+        let out = match op {
+            Op::Invalid(opcode) => return Err(VmFault::InvalidOpcode(opcode)),
+            // ext - the computer does nothing, it just dies
+            Op::Exit => false,
+            // lod <mem_address> <register>
+            Op::Load { addr, reg } => {
+                let val: u64 = self.memory_controller.read(addr)?;
+                self.write_to_reg(reg, val)?;
+                true
+            }
+            // wrt <register> <mem_address>
+            Op::Store { reg, addr } => {
+                let out = self.read_from_reg(reg)?;
+                self.memory_controller.write(addr, out)?;
+                true
+            }
+            // add, sub, mul, div, mod, and, or, xor, not, shl, shr, rol, ror
+            Op::Alu {
+                op,
+                left,
+                right,
+                out,
+            } => self.binary_op(op, left, right, out)?,
+            // spc <u32_value> - sets the program counter to the u32 in the instruction
+            Op::Jump(target) => {
+                self.write_to_program_counter(target);
+                true
+            }
+            // li/lis <reg> <imm32> - sets the register to the constant
+            Op::Immediate { reg, val } => {
+                self.write_to_reg(reg, val)?;
+                true
+            }
+            // ldr, ldo, ldp - loads through the address in a register
+            Op::LoadFrom {
+                base,
+                offset,
+                reg,
+                post_inc,
+            } => {
+                let addr = self.effective_address(base, offset)?;
+                self.read_from_reg(reg)?;
+                let val = self.memory_controller.read(addr)?;
+                if post_inc {
+                    self.write_to_reg(base, addr as u64 + 1)?;
+                }
+                self.write_to_reg(reg, val)?;
+                true
+            }
+            // str, sto, stp - stores through the address in a register
+            Op::StoreTo {
+                reg,
+                base,
+                offset,
+                post_inc,
+            } => {
+                let addr = self.effective_address(base, offset)?;
+                let val = self.read_from_reg(reg)?;
+                self.memory_controller.write(addr, val)?;
+                if post_inc {
+                    self.write_to_reg(base, addr as u64 + 1)?;
+                }
+                true
+            }
+            // lb, lbu, lh, lhu, lw, lwu - loads part of a word, extended to 64 bits
+            Op::LoadBytes {
+                base,
+                offset,
+                reg,
+                size,
+                signed,
+            } => {
+                let (addr, shift) = self.byte_address(base, offset, size)?;
+                self.read_from_reg(reg)?;
+                let bits = size * 8;
+                let val = self.memory_controller.read(addr)? >> shift;
+                // move the value to the top and back down to extend it
+                let val = match signed {
+                    true => ((val << (64 - bits)) as i64 >> (64 - bits)) as u64,
+                    false => val << (64 - bits) >> (64 - bits),
+                };
+                self.write_to_reg(reg, val)?;
+                true
+            }
+            // sb, sh, sw - stores the low bytes of a register into part of a word
+            Op::StoreBytes {
+                reg,
+                base,
+                offset,
+                size,
+            } => {
+                let (addr, shift) = self.byte_address(base, offset, size)?;
+                let mask = (u64::MAX >> (64 - size * 8)) << shift;
+                let val = self.read_from_reg(reg)? << shift;
+                // peek, a store shouldn't need read permission or set off a device's read
+                let old = self.memory_controller.peek(addr)?;
+                self.memory_controller
+                    .write(addr, old & !mask | val & mask)?;
+                true
+            }
+            // hostcall <id> - runs the host function registered as id
+            Op::HostCall(id) => {
+                self.call_host(id)?;
+                true
+            }
+            // clra - sets every register to zero
+            Op::ClearAll => {
+                self.reg_array = [0_u64; 8];
+                self.registers_written = u8::MAX;
+                true
+            }
+            // clr <reg_addr> - sets this register to zero
+            Op::Clear(reg) => {
+                self.write_to_reg(reg, 0)?;
+                true
+            }
+            // rw <reg1> <reg2> - writes the value of register 1 to register 2
+            Op::Move { from, to } => {
+                self.write_to_reg(to, self.read_from_reg(from)?)?;
+                true
+            }
+            // ieqe <reg1> <reg2> <u32_program_counter>
+            Op::IfEq {
+                left,
+                right,
+                target,
+            } => {
+                if self.read_from_reg(left)? == self.read_from_reg(right)? {
+                    self.program_counter = target;
+                }
+                true
+            }
+            // icrr <reg> - adds one to the register
+            Op::Increment(reg) => {
+                let (out, flags) = alu::add(self.read_from_reg(reg)?, 1)?;
+                self.write_to_reg(reg, out)?;
+                self.flags = flags;
+                true
+            }
+            // cmp <reg1> <reg2> - sets the flags from reg1 - reg2, writes nothing
+            Op::Compare { left, right } => {
+                self.flags = alu::compare(self.read_from_reg(left)?, self.read_from_reg(right)?);
+                true
+            }
+            // b<cond> <u32_program_counter> - jumps if the flags meet the condition
+            Op::Branch { cond, target } => {
+                if cond.holds(self.flags) {
+                    self.write_to_program_counter(target);
+                }
+                true
+            }
+            // push <reg> - pushes the register onto the stack
+            Op::Push(reg) => {
+                let val = self.read_from_reg(reg)?;
+                self.push(val)?;
+                true
+            }
+            // pop <reg> - pops the top of the stack into the register
+            Op::Pop(reg) => {
+                self.read_from_reg(reg)?;
+                let val = self.pop()?;
+                self.write_to_reg(reg, val)?;
+                true
+            }
+            // call <u32_program_counter> - pushes the return address and jumps
+            Op::Call(target) => {
+                self.push(self.program_counter as u64)?;
+                self.write_to_program_counter(target);
+                true
+            }
+            // ret - pops the return address into the program counter
+            Op::Return => {
+                let addr = self.pop()?;
+                self.write_to_program_counter(addr as u32);
+                true
+            }
+            // iret - pops the status word and return address pushed on entry
+            Op::InterruptReturn => {
+                let sp = self.stack_pointer;
+                let (status, addr) = match self.pop().and_then(|s| Ok((s, self.pop()?))) {
+                    Ok(popped) => popped,
+                    Err(kind) => {
+                        self.stack_pointer = sp;
+                        return Err(kind);
+                    }
+                };
+                self.flags = Flags::from_bits(status);
+                self.interrupts.set_enabled(status & STATUS_INTERRUPTS != 0);
+                self.write_to_program_counter(addr as u32);
+                true
+            }
+            // ei - lets pending interrupts be delivered
+            Op::EnableInterrupts => {
+                self.interrupts.set_enabled(true);
+                true
+            }
+            // di - holds interrupts pending until the next ei or iret
+            Op::DisableInterrupts => {
+                self.interrupts.set_enabled(false);
+                true
+            }
+        };
+        Ok(out)
+    }
+
+    /// The <reg1> <reg2> <reg3> ALU form: reg3 = op(reg1, reg2), flags
+    /// updated. `not` is decoded with reg1 in both places.
+    fn binary_op(&mut self, op: AluOp, left: u8, right: u8, out: u8) -> Result<bool, VmFault> {
+        let (val, flags) = op(self.read_from_reg(left)?, self.read_from_reg(right)?)?;
+        self.write_to_reg(out, val)?;
+        self.flags = flags;
+
+        Ok(true)
+    }
+
+    /// The register `base` plus `offset`, if that lands on a u32 address
+    fn effective_address(&self, base: u8, offset: i32) -> Result<u32, VmFault> {
+        let base = self.read_from_reg(base)?;
+        base.checked_add_signed(offset as i64)
+            .and_then(|addr| u32::try_from(addr).ok())
+            .ok_or(VmFault::MemoryOutOfRange(
+                base.wrapping_add_signed(offset as i64) as u32,
+            ))
+    }
+
+    /// The memory word and bit shift of a `size` byte access at byte
+    /// address `base + offset`
+    fn byte_address(&self, base: u8, offset: i32, size: u32) -> Result<(u32, u32), VmFault> {
+        let base = self.read_from_reg(base)?;
+        let addr = base.wrapping_add_signed(offset as i64);
+        if addr % size as u64 != 0 {
+            return Err(VmFault::Misaligned(addr));
+        }
+        let word = base
+            .checked_add_signed(offset as i64)
+            .and_then(|addr| u32::try_from(addr / 8).ok())
+            .ok_or(VmFault::MemoryOutOfRange((addr / 8) as u32))?;
+        Ok((word, (addr % 8) as u32 * 8))
+    }
+
+    fn push(&mut self, val: u64) -> Result<(), VmFault> {
+        if self.stack_pointer <= self.stack_base || self.stack_pointer > self.stack_top {
+            return Err(VmFault::StackOverflow(self.stack_pointer));
+        }
+        self.memory_controller.write(self.stack_pointer - 1, val)?;
+        self.stack_pointer -= 1;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<u64, VmFault> {
+        if self.stack_pointer >= self.stack_top || self.stack_pointer < self.stack_base {
+            return Err(VmFault::StackUnderflow(self.stack_pointer));
+        }
+        let val = self.memory_controller.read(self.stack_pointer)?;
+        self.stack_pointer += 1;
+        Ok(val)
+    }
+
+    fn incr(&mut self) {
+        self.program_counter = self.program_counter.wrapping_add(1);
+    }
+}
+
+/**
+ * Collects everything needed to bring up a machine: its memory, the
+ * programs to load, where execution starts and any registers that should be
+ * preset.
+ *
+ * ```
+ * # use rust_vm_project::{FlatMemory, CPU};
+ * # let words = vec![0_u64; 4];
+ * let mut cpu = CPU::builder(FlatMemory::new(100))
+ *     .program(&words, 0)
+ *     .entry(3)
+ *     .build()?;
+ * # Ok::<(), rust_vm_project::VmFault>(())
+ * ```
+ */
+pub struct MachineBuilder<M: MemoryBackend> {
+    memory: M,
+    programs: Vec<(Vec<u64>, usize)>,
+    entry: u32,
+    registers: Vec<(u8, u64)>,
+    stack: Option<std::ops::Range<u32>>,
+    vectors: Option<u32>,
+    costs: CostTable,
+    devices: Vec<(u32, u32, Box<dyn Device>)>,
+    host_calls: Vec<(u32, HostFn<M>)>,
+    console: bool,
+    regions: Vec<(u32, u32, Permissions)>,
+    default_permissions: Permissions,
+}
+
+impl<M: MemoryBackend> MachineBuilder<M> {
+    pub fn new(memory: M) -> Self {
+        Self {
+            memory,
+            programs: Vec::new(),
+            entry: 0,
+            registers: Vec::new(),
+            stack: None,
+            vectors: None,
+            costs: CostTable::default(),
+            devices: Vec::new(),
+            host_calls: Vec::new(),
+            console: false,
+            regions: Vec::new(),
+            default_permissions: Permissions::RWX,
+        }
+    }
+
+    /// Loads `words` into memory starting at `idx`, can be called more than once
+    pub fn program(mut self, words: &[u64], idx: usize) -> Self {
+        self.programs.push((words.to_vec(), idx));
+        self
+    }
+
+    /// Where the program counter starts
+    pub fn entry(mut self, pc: u32) -> Self {
+        self.entry = pc;
+        self
+    }
+
+    /// Presets a register, out of range indices are reported by `build`
+    pub fn register(mut self, idx: u8, val: u64) -> Self {
+        self.registers.push((idx, val));
+        self
+    }
+
+    /// Where the stack lives, defaults to the top of memory (at most 64 words)
+    pub fn stack(mut self, region: std::ops::Range<u32>) -> Self {
+        self.stack = Some(region);
+        self
+    }
+
+    /// Where the vector table lives, interrupts and fault handlers are off without one
+    pub fn vectors(mut self, base: u32) -> Self {
+        self.vectors = Some(base);
+        self
+    }
+
+    /// What each opcode costs against `CPU::run_with_budget`, 1 each by default
+    pub fn costs(mut self, costs: CostTable) -> Self {
+        self.costs = costs;
+        self
+    }
+
+    /// Maps `device` at `base..base + len`, see `MemoryController::map_device`
+    pub fn device(mut self, base: u32, len: u32, device: Box<dyn Device>) -> Self {
+        self.devices.push((base, len, device));
+        self
+    }
+
+    /// Registers `f` as host call `id`, see `CPU::register_hostcall`
+    pub fn hostcall(
+        mut self,
+        id: u32,
+        f: impl FnMut(&mut CPU<M>) -> Result<(), VmFault> + 'static,
+    ) -> Self {
+        self.host_calls.push((id, Box::new(f)));
+        self
+    }
+
+    /// Attaches stdin, stdout and the halt port, see `device::attach_console`
+    pub fn console(mut self) -> Self {
+        self.console = true;
+        self
+    }
+
+    /// Gives `base..base + len` its own permissions, see `MemoryController::protect`
+    pub fn protect(mut self, base: u32, len: u32, permissions: Permissions) -> Self {
+        self.regions.push((base, len, permissions));
+        self
+    }
+
+    /// What memory outside every `protect`ed region allows, everything by default
+    pub fn default_permissions(mut self, permissions: Permissions) -> Self {
+        self.default_permissions = permissions;
+        self
+    }
+
+    pub fn build(self) -> Result<CPU<M>, VmFault> {
+        let words = self.memory.words();
+        let mut memory_controller = MemoryController::new_from(self.memory);
+        if self.console {
+            attach_console(&mut memory_controller);
+        }
+        for (base, len, device) in self.devices {
+            memory_controller.map_device(base, len, device);
+        }
+        for (words, idx) in &self.programs {
+            memory_controller.load_program_external(words, *idx)?;
+        }
+        for (base, len, permissions) in self.regions {
+            memory_controller.protect(base, len, permissions);
+        }
+        memory_controller.set_default_permissions(self.default_permissions);
+
+        let mut cpu = CPU::new(memory_controller);
+        cpu.costs = self.costs;
+        cpu.host_calls.extend(self.host_calls);
+        for (idx, val) in self.registers {
+            cpu.write_to_reg(idx, val)?;
+        }
+        if let Some(region) = self.stack {
+            if region.start > region.end || region.end as u64 > words {
+                return Err(VmFault::MemoryOutOfRange(region.end));
+            }
+            cpu.set_stack_region(region);
+        }
+        if let Some(base) = self.vectors {
+            if base as u64 + VECTORS as u64 > words {
+                return Err(VmFault::MemoryOutOfRange(base));
+            }
+            cpu.interrupts.set_vectors(Some(base));
+        }
+        cpu.program_counter = self.entry;
+        Ok(cpu)
+    }
+}
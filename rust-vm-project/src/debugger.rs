@@ -0,0 +1,414 @@
+use crate::asm::parse_number;
+use crate::disasm;
+use crate::fault::Fault;
+use crate::image::Symbol;
+use crate::undo::UndoLog;
+use crate::{MemoryBackend, CPU};
+use anyhow::{anyhow, Result};
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+
+const HELP: &str = "commands:
+    s, step [n]             execute n instructions (default 1)
+    c, continue             run until a breakpoint, exit or fault
+    rs, rstep [n]           step back n instructions (default 1)
+    rc, rcontinue           run backwards to the previous breakpoint
+    last <reg>              run backwards to the instruction that last wrote a register
+    history [bytes]         show how much history is kept, or change the limit
+    b, break <addr>         set a breakpoint, addresses can be labels
+    d, delete <addr>        remove a breakpoint
+    breaks                  list breakpoints
+    r, regs                 show registers, program counter, stack pointer and flags
+    set <reg|pc|sp> <value> change a register, the program counter or stack pointer
+    x <addr> [count]        examine memory
+    w <addr> <value>        write a word of memory, code included
+    l, list [count]         disassemble around the program counter
+    save <file>             write a snapshot of the machine
+    restore <file>          go back to a snapshot
+    q, quit                 leave the debugger";
+
+/// Why the debugger handed control back
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Stop {
+    Stepped,         // ran the requested number of instructions
+    Breakpoint(u32), // about to execute a breakpointed address
+    Exited,          // the program executed `ext`
+    Faulted(Fault),  // the program faulted, the machine is left as it was
+    HistoryStart,    // stepping back ran out of recorded history
+}
+
+/**
+ * Breakpoints and stepping on top of `CPU::step`, plus a small command
+ * language so it can be driven interactively or from a script file. Every
+ * step goes into an `UndoLog` so the program can be run backwards too.
+ */
+pub struct Debugger<M: MemoryBackend> {
+    cpu: CPU<M>,
+    breakpoints: BTreeSet<u32>,
+    symbols: Vec<Symbol>,
+    exited: bool,
+    history: UndoLog,
+}
+
+impl<M: MemoryBackend> Debugger<M> {
+    pub fn new(cpu: CPU<M>) -> Self {
+        Self {
+            cpu,
+            breakpoints: BTreeSet::new(),
+            symbols: Vec::new(),
+            exited: false,
+            history: UndoLog::default(),
+        }
+    }
+
+    /// Bounds the history kept for stepping back, see `UndoLog`
+    pub fn with_history_limit(mut self, bytes: usize) -> Self {
+        self.history.set_limit(bytes);
+        self
+    }
+
+    pub fn history(&self) -> &UndoLog {
+        &self.history
+    }
+
+    /// Lets commands take labels as well as raw addresses
+    pub fn with_symbols(mut self, symbols: Vec<Symbol>) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    pub fn cpu(&self) -> &CPU<M> {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut CPU<M> {
+        &mut self.cpu
+    }
+
+    pub fn into_cpu(self) -> CPU<M> {
+        self.cpu
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u32) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u32) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u32> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    /// Executes up to `n` instructions, stopping early on exit, fault or breakpoint
+    pub fn step_n(&mut self, n: usize) -> Stop {
+        for i in 0..n {
+            if i > 0 && self.breakpoints.contains(&self.cpu.program_counter()) {
+                return Stop::Breakpoint(self.cpu.program_counter());
+            }
+            if let Some(stop) = self.single_step() {
+                return stop;
+            }
+        }
+        Stop::Stepped
+    }
+
+    /// Runs until the next breakpoint, stepping off the current one first
+    pub fn cont(&mut self) -> Stop {
+        loop {
+            if let Some(stop) = self.single_step() {
+                return stop;
+            }
+            if self.breakpoints.contains(&self.cpu.program_counter()) {
+                return Stop::Breakpoint(self.cpu.program_counter());
+            }
+        }
+    }
+
+    fn single_step(&mut self) -> Option<Stop> {
+        if self.exited {
+            return Some(Stop::Exited);
+        }
+        match self.cpu.step_recorded(&mut self.history) {
+            Ok(true) => None,
+            Ok(false) => {
+                self.exited = true;
+                Some(Stop::Exited)
+            }
+            Err(fault) => Some(Stop::Faulted(fault)),
+        }
+    }
+
+    /// Steps back up to `n` instructions, stopping early at a breakpoint
+    pub fn step_back_n(&mut self, n: usize) -> Stop {
+        for i in 0..n {
+            if i > 0 && self.breakpoints.contains(&self.cpu.program_counter()) {
+                return Stop::Breakpoint(self.cpu.program_counter());
+            }
+            if !self.single_step_back() {
+                return Stop::HistoryStart;
+            }
+        }
+        Stop::Stepped
+    }
+
+    /// Runs backwards to the previous breakpoint
+    pub fn reverse_continue(&mut self) -> Stop {
+        loop {
+            if !self.single_step_back() {
+                return Stop::HistoryStart;
+            }
+            if self.breakpoints.contains(&self.cpu.program_counter()) {
+                return Stop::Breakpoint(self.cpu.program_counter());
+            }
+        }
+    }
+
+    /// Runs backwards until just before the instruction that last wrote `reg`
+    pub fn back_to_write(&mut self, reg: u8) -> Stop {
+        loop {
+            let found = self.history.last_wrote(reg);
+            if !self.single_step_back() {
+                return Stop::HistoryStart;
+            }
+            if found {
+                return Stop::Stepped;
+            }
+        }
+    }
+
+    fn single_step_back(&mut self) -> bool {
+        let stepped = self.cpu.step_back(&mut self.history);
+        if stepped {
+            self.exited = false;
+        }
+        stepped
+    }
+
+    /// Reads commands until `quit` or end of input, prompting if `interactive`
+    pub fn repl(
+        &mut self,
+        input: impl BufRead,
+        out: &mut impl Write,
+        interactive: bool,
+    ) -> Result<()> {
+        if interactive {
+            write!(out, "(rvm) ")?;
+            out.flush()?;
+        }
+        for line in input.lines() {
+            let line = line?;
+            if !interactive && !line.trim().is_empty() {
+                writeln!(out, "(rvm) {}", line.trim())?;
+            }
+            if !self.command(&line, out)? {
+                break;
+            }
+            if interactive {
+                write!(out, "(rvm) ")?;
+                out.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs one command, returns `Ok(false)` when the user asked to quit
+    pub fn command(&mut self, line: &str, out: &mut impl Write) -> Result<bool> {
+        let args: Vec<&str> = line
+            .split('#')
+            .next()
+            .unwrap_or("")
+            .split_whitespace()
+            .collect();
+        match self.dispatch(&args, out) {
+            Ok(keep_going) => Ok(keep_going),
+            Err(e) => {
+                writeln!(out, "error: {}", e)?;
+                Ok(true)
+            }
+        }
+    }
+
+    fn dispatch(&mut self, args: &[&str], out: &mut impl Write) -> Result<bool> {
+        match args {
+            [] => {}
+            ["s" | "step"] => {
+                let stop = self.step_n(1);
+                self.report(stop, out)?;
+            }
+            ["s" | "step", n] => {
+                let stop = self.step_n(parse_number(n)? as usize);
+                self.report(stop, out)?;
+            }
+            ["c" | "continue"] => {
+                let stop = self.cont();
+                self.report(stop, out)?;
+            }
+            ["rs" | "rstep"] => {
+                let stop = self.step_back_n(1);
+                self.report(stop, out)?;
+            }
+            ["rs" | "rstep", n] => {
+                let stop = self.step_back_n(parse_number(n)? as usize);
+                self.report(stop, out)?;
+            }
+            ["rc" | "rcontinue"] => {
+                let stop = self.reverse_continue();
+                self.report(stop, out)?;
+            }
+            ["last", reg] => {
+                let stop = self.back_to_write(parse_register(reg)?);
+                self.report(stop, out)?;
+            }
+            ["history"] => writeln!(
+                out,
+                "{} instructions, {} of {} bytes",
+                self.history.len(),
+                self.history.bytes(),
+                self.history.limit()
+            )?,
+            ["history", bytes] => self.history.set_limit(parse_number(bytes)? as usize),
+            ["b" | "break", addr] => {
+                let addr = self.parse_addr(addr)?;
+                self.add_breakpoint(addr);
+                writeln!(out, "breakpoint at {}", addr)?;
+            }
+            ["d" | "delete", addr] => {
+                let addr = self.parse_addr(addr)?;
+                if !self.remove_breakpoint(addr) {
+                    return Err(anyhow!("no breakpoint at {}", addr));
+                }
+            }
+            ["breaks"] => {
+                for addr in self.breakpoints() {
+                    writeln!(out, "{}", addr)?;
+                }
+            }
+            ["r" | "regs"] => self.show_registers(out)?,
+            ["set", "pc", value] => {
+                let pc = self.parse_addr(value)?;
+                self.cpu.set_program_counter(pc);
+                self.exited = false;
+            }
+            ["set", "sp", value] => {
+                let sp = self.parse_addr(value)?;
+                self.cpu.set_stack_pointer(sp);
+            }
+            ["set", reg, value] => {
+                let reg = parse_register(reg)?;
+                let value = parse_number(value)?;
+                self.cpu.set_register(reg, value)?;
+            }
+            ["x", addr] => self.examine(self.parse_addr(addr)?, 1, out)?,
+            ["x", addr, count] => {
+                let count = parse_number(count)? as u32;
+                self.examine(self.parse_addr(addr)?, count, out)?
+            }
+            ["w", addr, value] => {
+                let addr = self.parse_addr(addr)?;
+                let value = parse_number(value)?;
+                self.cpu.memory_controller_mut().poke(addr, value)?;
+            }
+            ["l" | "list"] => self.list(8, out)?,
+            ["l" | "list", count] => self.list(parse_number(count)? as u32, out)?,
+            ["save", path] => {
+                self.cpu.save_snapshot(path)?;
+                writeln!(out, "saved {}", path)?;
+            }
+            ["restore", path] => {
+                self.cpu.restore_snapshot(path)?;
+                self.history.clear();
+                self.exited = false;
+                self.list(1, out)?;
+            }
+            ["q" | "quit"] => return Ok(false),
+            ["h" | "help"] => writeln!(out, "{}", HELP)?,
+            _ => return Err(anyhow!("unknown command `{}`, try `help`", args.join(" "))),
+        }
+        Ok(true)
+    }
+
+    fn report(&self, stop: Stop, out: &mut impl Write) -> Result<()> {
+        match stop {
+            Stop::Stepped => {}
+            Stop::Breakpoint(addr) => writeln!(out, "breakpoint at {}", addr)?,
+            Stop::Exited => writeln!(out, "program exited")?,
+            Stop::Faulted(fault) => writeln!(out, "{}", fault)?,
+            Stop::HistoryStart => writeln!(out, "no more history to step back through")?,
+        }
+        self.list(1, out)
+    }
+
+    fn show_registers(&self, out: &mut impl Write) -> Result<()> {
+        for (idx, val) in self.cpu.registers().iter().enumerate() {
+            writeln!(out, "r{} = {}", idx, val)?;
+        }
+        writeln!(out, "pc = {}", self.cpu.program_counter())?;
+        writeln!(out, "sp = {}", self.cpu.stack_pointer())?;
+        writeln!(out, "flags = {:?}", self.cpu.flags())?;
+        writeln!(out, "cycles = {}", self.cpu.cycles())?;
+        let interrupts = self.cpu.interrupts();
+        writeln!(
+            out,
+            "interrupts = {}, pending {:#06x}",
+            if interrupts.enabled() { "on" } else { "off" },
+            interrupts.pending()
+        )?;
+        Ok(())
+    }
+
+    fn examine(&self, addr: u32, count: u32, out: &mut impl Write) -> Result<()> {
+        for offset in 0..count {
+            let addr = addr
+                .checked_add(offset)
+                .ok_or_else(|| anyhow!("address overflow"))?;
+            let val = self.cpu.memory_controller().peek(addr)?;
+            writeln!(out, "{:>6}: {}", addr, val)?;
+        }
+        Ok(())
+    }
+
+    /// Disassembles `count` words starting a couple before the program counter
+    fn list(&self, count: u32, out: &mut impl Write) -> Result<()> {
+        let pc = self.cpu.program_counter();
+        let start = if count > 1 { pc.saturating_sub(2) } else { pc };
+        // stops at the end of memory, which can be the end of the address space
+        let words: Vec<u64> = (start..=u32::MAX)
+            .take(count as usize)
+            .map_while(|addr| self.cpu.memory_controller().peek(addr).ok())
+            .collect();
+
+        for line in disasm::disassemble(&words, start) {
+            let marker = if line.addr == pc { "=>" } else { "  " };
+            let bp = if self.breakpoints.contains(&line.addr) {
+                "*"
+            } else {
+                " "
+            };
+            if let Some(symbol) = self.symbols.iter().find(|s| s.addr == line.addr) {
+                writeln!(out, "{}:", symbol.name)?;
+            }
+            writeln!(out, "{}{}{}", marker, bp, line)?;
+        }
+        Ok(())
+    }
+
+    fn parse_addr(&self, arg: &str) -> Result<u32> {
+        if let Some(symbol) = self.symbols.iter().find(|s| s.name == arg) {
+            return Ok(symbol.addr);
+        }
+        u32::try_from(parse_number(arg)?).map_err(|_| anyhow!("`{}` does not fit in a u32", arg))
+    }
+}
+
+fn parse_register(arg: &str) -> Result<u8> {
+    arg.strip_prefix('r')
+        .and_then(|digits| digits.parse::<u8>().ok())
+        .filter(|reg| *reg < 8)
+        .ok_or_else(|| anyhow!("bad register `{}`, expected r0..r7, pc or sp", arg))
+}
@@ -0,0 +1,307 @@
+use crate::alu::{self, AluResult, Flags};
+use crate::instruction::{deserialize_instruction, deserialize_u32_array, Instruction};
+use crate::memory::PAGE_WORDS;
+use std::collections::HashMap;
+
+/// The three register ALU form, `not` included
+pub(crate) type AluOp = fn(u64, u64) -> AluResult;
+
+/**
+ * One instruction with its operands pulled out of the word, what `execute`
+ * actually runs. Registers are left unchecked, a bad index still faults
+ * when the instruction runs, and so does a bad opcode.
+ */
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum Op {
+    Invalid(u8),
+    Exit,
+    Load {
+        addr: u32,
+        reg: u8,
+    },
+    Store {
+        reg: u8,
+        addr: u32,
+    },
+    Alu {
+        op: AluOp,
+        left: u8,
+        right: u8,
+        out: u8,
+    },
+    Jump(u32),
+    HostCall(u32),
+    Immediate {
+        reg: u8,
+        val: u64, // already extended
+    },
+    LoadFrom {
+        base: u8,
+        offset: i32,
+        reg: u8,
+        post_inc: bool,
+    },
+    StoreTo {
+        reg: u8,
+        base: u8,
+        offset: i32,
+        post_inc: bool,
+    },
+    LoadBytes {
+        base: u8,
+        offset: i32,
+        reg: u8,
+        size: u32, // 1, 2 or 4
+        signed: bool,
+    },
+    StoreBytes {
+        reg: u8,
+        base: u8,
+        offset: i32,
+        size: u32,
+    },
+    ClearAll,
+    Clear(u8),
+    Move {
+        from: u8,
+        to: u8,
+    },
+    IfEq {
+        left: u8,
+        right: u8,
+        target: u32,
+    },
+    Increment(u8),
+    Compare {
+        left: u8,
+        right: u8,
+    },
+    Branch {
+        cond: Cond,
+        target: u32,
+    },
+    Push(u8),
+    Pop(u8),
+    Call(u32),
+    Return,
+    InterruptReturn,
+    EnableInterrupts,
+    DisableInterrupts,
+}
+
+/// What a conditional branch tests, see `Instruction::BranchEq` and friends
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Cond {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    LtU,
+    LeU,
+    GtU,
+    GeU,
+}
+
+impl Cond {
+    pub(crate) fn holds(self, flags: Flags) -> bool {
+        let signed_lt = flags.negative != flags.overflow;
+        match self {
+            Cond::Eq => flags.zero,
+            Cond::Ne => !flags.zero,
+            Cond::Lt => signed_lt,
+            Cond::Le => flags.zero || signed_lt,
+            Cond::Gt => !flags.zero && !signed_lt,
+            Cond::Ge => !signed_lt,
+            Cond::LtU => flags.carry,
+            Cond::LeU => flags.carry || flags.zero,
+            Cond::GtU => !flags.carry && !flags.zero,
+            Cond::GeU => !flags.carry,
+        }
+    }
+}
+
+impl Op {
+    pub(crate) fn decode(raw: u64) -> Self {
+        let bytes = raw.to_le_bytes();
+        let Ok(instruction) = deserialize_instruction(bytes[0]) else {
+            return Op::Invalid(bytes[0]);
+        };
+        let u32_at = |idx| deserialize_u32_array(idx, &bytes);
+        let alu = |op: AluOp| Op::Alu {
+            op,
+            left: bytes[1],
+            right: bytes[2],
+            out: bytes[3],
+        };
+        let load = |offset, reg, post_inc| Op::LoadFrom {
+            base: bytes[1],
+            offset,
+            reg,
+            post_inc,
+        };
+        let store = |offset, post_inc| Op::StoreTo {
+            reg: bytes[1],
+            base: bytes[2],
+            offset,
+            post_inc,
+        };
+        let load_bytes = |size, signed| Op::LoadBytes {
+            base: bytes[1],
+            offset: u32_at(2) as i32,
+            reg: bytes[6],
+            size,
+            signed,
+        };
+        let store_bytes = |size| Op::StoreBytes {
+            reg: bytes[1],
+            base: bytes[2],
+            offset: u32_at(3) as i32,
+            size,
+        };
+        let branch = |cond| Op::Branch {
+            cond,
+            target: u32_at(1),
+        };
+        match instruction {
+            Instruction::Exit => Op::Exit,
+            Instruction::LoadFromMem => Op::Load {
+                addr: u32_at(1),
+                reg: bytes[5],
+            },
+            Instruction::WriteToMem => Op::Store {
+                reg: bytes[1],
+                addr: u32_at(2),
+            },
+            Instruction::Add => alu(alu::add),
+            Instruction::Sub => alu(alu::sub),
+            Instruction::SetProgramCounter => Op::Jump(u32_at(1)),
+            Instruction::ClearAllRegisters => Op::ClearAll,
+            Instruction::ClearRegister => Op::Clear(bytes[1]),
+            Instruction::RegisterWrite => Op::Move {
+                from: bytes[1],
+                to: bytes[2],
+            },
+            Instruction::IfEqSPCElsePass => Op::IfEq {
+                left: bytes[1],
+                right: bytes[2],
+                target: u32_at(3),
+            },
+            Instruction::IncrementReg => Op::Increment(bytes[1]),
+            Instruction::Mul => alu(alu::mul),
+            Instruction::Div => alu(alu::div),
+            Instruction::Mod => alu(alu::rem),
+            Instruction::And => alu(alu::and),
+            Instruction::Or => alu(alu::or),
+            Instruction::Xor => alu(alu::xor),
+            // only two operands, byte 2 isn't a register and is never read
+            Instruction::Not => Op::Alu {
+                op: alu::not,
+                left: bytes[1],
+                right: bytes[1],
+                out: bytes[3],
+            },
+            Instruction::ShiftLeft => alu(alu::shl),
+            Instruction::ShiftRight => alu(alu::shr),
+            Instruction::RotateLeft => alu(alu::rol),
+            Instruction::RotateRight => alu(alu::ror),
+            Instruction::Compare => Op::Compare {
+                left: bytes[1],
+                right: bytes[2],
+            },
+            Instruction::BranchEq => branch(Cond::Eq),
+            Instruction::BranchNe => branch(Cond::Ne),
+            Instruction::BranchLt => branch(Cond::Lt),
+            Instruction::BranchLe => branch(Cond::Le),
+            Instruction::BranchGt => branch(Cond::Gt),
+            Instruction::BranchGe => branch(Cond::Ge),
+            Instruction::BranchLtU => branch(Cond::LtU),
+            Instruction::BranchLeU => branch(Cond::LeU),
+            Instruction::BranchGtU => branch(Cond::GtU),
+            Instruction::BranchGeU => branch(Cond::GeU),
+            Instruction::Push => Op::Push(bytes[1]),
+            Instruction::Pop => Op::Pop(bytes[1]),
+            Instruction::Call => Op::Call(u32_at(1)),
+            Instruction::Return => Op::Return,
+            Instruction::InterruptReturn => Op::InterruptReturn,
+            Instruction::EnableInterrupts => Op::EnableInterrupts,
+            Instruction::DisableInterrupts => Op::DisableInterrupts,
+            Instruction::LoadImmediate => Op::Immediate {
+                reg: bytes[1],
+                val: u32_at(2) as u64,
+            },
+            Instruction::LoadSignedImm => Op::Immediate {
+                reg: bytes[1],
+                val: u32_at(2) as i32 as i64 as u64,
+            },
+            Instruction::LoadIndirect => load(0, bytes[2], false),
+            Instruction::LoadOffset => load(u32_at(2) as i32, bytes[6], false),
+            Instruction::LoadPostInc => load(0, bytes[2], true),
+            Instruction::StoreIndirect => store(0, false),
+            Instruction::StoreOffset => store(u32_at(3) as i32, false),
+            Instruction::StorePostInc => store(0, true),
+            Instruction::LoadByte => load_bytes(1, true),
+            Instruction::LoadByteU => load_bytes(1, false),
+            Instruction::LoadHalf => load_bytes(2, true),
+            Instruction::LoadHalfU => load_bytes(2, false),
+            Instruction::LoadWord => load_bytes(4, true),
+            Instruction::LoadWordU => load_bytes(4, false),
+            Instruction::StoreByte => store_bytes(1),
+            Instruction::StoreHalf => store_bytes(2),
+            Instruction::StoreWord => store_bytes(4),
+            Instruction::HostCall => Op::HostCall(u32_at(1)),
+        }
+    }
+}
+
+type Page = Box<[Option<(u64, Op)>]>;
+
+/**
+ * Decoded instructions by address, filled in as they're first fetched and
+ * kept in pages like `PagedMemory` so scattered code stays cheap. Whoever
+ * owns it has to `invalidate` every address that gets written.
+ */
+#[derive(Debug, Default, Clone)]
+pub(crate) struct DecodeCache {
+    pages: HashMap<u32, Page>, // keyed by address / PAGE_WORDS
+}
+
+impl DecodeCache {
+    /// The word and decoding cached for `idx`, if any
+    pub(crate) fn get(&self, idx: u32) -> Option<(u64, Op)> {
+        let page = self.pages.get(&(idx / PAGE_WORDS as u32))?;
+        page[idx as usize % PAGE_WORDS]
+    }
+
+    pub(crate) fn insert(&mut self, idx: u32, raw: u64, op: Op) {
+        let page = self
+            .pages
+            .entry(idx / PAGE_WORDS as u32)
+            .or_insert_with(|| vec![None; PAGE_WORDS].into_boxed_slice());
+        page[idx as usize % PAGE_WORDS] = Some((raw, op));
+    }
+
+    pub(crate) fn invalidate(&mut self, idx: u32) {
+        if let Some(page) = self.pages.get_mut(&(idx / PAGE_WORDS as u32)) {
+            page[idx as usize % PAGE_WORDS] = None;
+        }
+    }
+
+    /// Invalidates every address in `start..end`, only looking at pages
+    /// that have something cached
+    pub(crate) fn invalidate_range(&mut self, start: u64, end: u64) {
+        for (&page, slots) in self.pages.iter_mut() {
+            let first = page as u64 * PAGE_WORDS as u64;
+            let from = start.max(first);
+            let to = end.min(first + PAGE_WORDS as u64);
+            for addr in from..to {
+                slots[(addr - first) as usize] = None;
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.pages.clear();
+    }
+}
@@ -0,0 +1,275 @@
+use crate::fault::VmFault;
+use crate::{MemoryBackend, MemoryController};
+use anyhow::{anyhow, Result};
+use std::cell::RefCell;
+use std::io::{Read, Write};
+use std::rc::Rc;
+
+/// `wrt` here prints the low byte as a character, at +1 prints the number
+pub const CONSOLE_OUT: u32 = 0xffff_ff00;
+/// `lod` from here reads the next input byte, u64::MAX once input runs out
+pub const CONSOLE_IN: u32 = 0xffff_ff10;
+/// `wrt` here stops the machine with that exit code
+pub const HALT: u32 = 0xffff_ff20;
+/// `wrt` here sets the timer period, 0 stops it, +1 reads the cycles left
+pub const TIMER: u32 = 0xffff_ff30;
+
+/**
+ * Something living on the memory bus. `MemoryController::map_device` hands
+ * it a range of addresses, reads and writes there get routed to the device
+ * with the offset into that range instead of going to RAM.
+ */
+pub trait Device {
+    fn read(&mut self, offset: u32) -> Result<u64, VmFault>;
+
+    fn write(&mut self, offset: u32, val: u64) -> Result<(), VmFault>;
+
+    /// What a debugger sees, must not have side effects like consuming input
+    fn peek(&self, _offset: u32) -> u64 {
+        0
+    }
+
+    /// Set once the device wants the machine to stop
+    fn exit_code(&self) -> Option<u64> {
+        None
+    }
+
+    /// Called once per cycle before the instruction is fetched, returns an
+    /// IRQ line to raise
+    fn tick(&mut self) -> Option<u8> {
+        None
+    }
+
+    /// Internal state for a snapshot, streams and the like are left out
+    fn save(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Fails if `restore` wouldn't take `state`, without changing anything
+    fn check_state(&self, _state: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Takes back what `save` produced, anything `check_state` passes
+    fn restore(&mut self, _state: &[u8]) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Character and number output, to stdout or anything else that's `Write`
+pub struct ConsoleOut {
+    out: Box<dyn Write>,
+}
+
+impl ConsoleOut {
+    pub fn new(out: Box<dyn Write>) -> Self {
+        Self { out }
+    }
+
+    pub fn stdout() -> Self {
+        Self::new(Box::new(std::io::stdout()))
+    }
+
+    /// Output collected in memory, the embedder keeps the other handle
+    pub fn buffered() -> (Self, Rc<RefCell<Vec<u8>>>) {
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        let out = Self::new(Box::new(SharedBuffer(buffer.clone())));
+        (out, buffer)
+    }
+}
+
+impl Device for ConsoleOut {
+    fn read(&mut self, _offset: u32) -> Result<u64, VmFault> {
+        Ok(0)
+    }
+
+    fn write(&mut self, offset: u32, val: u64) -> Result<(), VmFault> {
+        // a broken pipe shouldn't take the machine down with it
+        let _ = match offset {
+            0 => self.out.write_all(&[val as u8]),
+            _ => writeln!(self.out, "{}", val),
+        };
+        let _ = self.out.flush();
+        Ok(())
+    }
+}
+
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Byte at a time input, from stdin or a fixed buffer
+pub struct ConsoleIn {
+    input: Box<dyn Read>,
+}
+
+impl ConsoleIn {
+    pub fn new(input: Box<dyn Read>) -> Self {
+        Self { input }
+    }
+
+    pub fn stdin() -> Self {
+        Self::new(Box::new(std::io::stdin()))
+    }
+
+    pub fn from_buffer(bytes: Vec<u8>) -> Self {
+        Self::new(Box::new(std::io::Cursor::new(bytes)))
+    }
+}
+
+impl Device for ConsoleIn {
+    fn read(&mut self, _offset: u32) -> Result<u64, VmFault> {
+        let mut byte = [0_u8; 1];
+        match self.input.read(&mut byte) {
+            Ok(1) => Ok(byte[0] as u64),
+            _ => Ok(u64::MAX),
+        }
+    }
+
+    fn write(&mut self, _offset: u32, _val: u64) -> Result<(), VmFault> {
+        Ok(())
+    }
+}
+
+/// Writing any value stops the machine with it as the exit code
+#[derive(Default)]
+pub struct HaltPort {
+    code: Option<u64>,
+}
+
+impl Device for HaltPort {
+    fn read(&mut self, _offset: u32) -> Result<u64, VmFault> {
+        Ok(self.code.unwrap_or(0))
+    }
+
+    fn write(&mut self, _offset: u32, val: u64) -> Result<(), VmFault> {
+        self.code = Some(val);
+        Ok(())
+    }
+
+    fn peek(&self, _offset: u32) -> u64 {
+        self.code.unwrap_or(0)
+    }
+
+    fn exit_code(&self) -> Option<u64> {
+        self.code
+    }
+
+    fn save(&self) -> Vec<u8> {
+        match self.code {
+            Some(code) => code.to_le_bytes().to_vec(),
+            None => Vec::new(),
+        }
+    }
+
+    fn check_state(&self, state: &[u8]) -> Result<()> {
+        match state.len() {
+            0 | 8 => Ok(()),
+            _ => Err(anyhow!("bad halt port state")),
+        }
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<()> {
+        self.check_state(state)?;
+        self.code = match state {
+            [] => None,
+            _ => Some(u64::from_le_bytes(state.try_into().unwrap())),
+        };
+        Ok(())
+    }
+}
+
+/// Raises its IRQ line every `period` cycles once a period is written
+pub struct Timer {
+    irq: u8,
+    period: u64,
+    remaining: u64,
+}
+
+impl Timer {
+    pub fn new(irq: u8) -> Self {
+        Self {
+            irq,
+            period: 0,
+            remaining: 0,
+        }
+    }
+}
+
+impl Device for Timer {
+    fn read(&mut self, offset: u32) -> Result<u64, VmFault> {
+        Ok(self.peek(offset))
+    }
+
+    fn write(&mut self, offset: u32, val: u64) -> Result<(), VmFault> {
+        if offset == 0 {
+            self.period = val;
+            self.remaining = val;
+        }
+        Ok(())
+    }
+
+    fn peek(&self, offset: u32) -> u64 {
+        match offset {
+            0 => self.period,
+            _ => self.remaining,
+        }
+    }
+
+    fn tick(&mut self) -> Option<u8> {
+        if self.period == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        if self.remaining > 0 {
+            return None;
+        }
+        self.remaining = self.period;
+        Some(self.irq)
+    }
+
+    fn save(&self) -> Vec<u8> {
+        let mut state = self.period.to_le_bytes().to_vec();
+        state.extend_from_slice(&self.remaining.to_le_bytes());
+        state
+    }
+
+    fn check_state(&self, state: &[u8]) -> Result<()> {
+        if state.len() != 16 {
+            return Err(anyhow!("bad timer state"));
+        }
+        let period = u64::from_le_bytes(state[..8].try_into().unwrap());
+        let remaining = u64::from_le_bytes(state[8..].try_into().unwrap());
+        // a running timer always has at least one cycle to go
+        if period > 0 && remaining == 0 {
+            return Err(anyhow!(
+                "timer with period {} has no cycles remaining",
+                period
+            ));
+        }
+        Ok(())
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<()> {
+        self.check_state(state)?;
+        self.period = u64::from_le_bytes(state[..8].try_into().unwrap());
+        self.remaining = u64::from_le_bytes(state[8..].try_into().unwrap());
+        Ok(())
+    }
+}
+
+/// Maps stdout, stdin and a halt port at `CONSOLE_OUT`, `CONSOLE_IN` and `HALT`
+pub fn attach_console<M: MemoryBackend>(mc: &mut MemoryController<M>) {
+    mc.map_device(CONSOLE_OUT, 2, Box::new(ConsoleOut::stdout()));
+    mc.map_device(CONSOLE_IN, 1, Box::new(ConsoleIn::stdin()));
+    mc.map_device(HALT, 1, Box::new(HaltPort::default()));
+}
@@ -0,0 +1,99 @@
+use crate::{deserialize_u32_array, MemoryBackend, Operand};
+use std::fmt;
+use std::ops::Range;
+
+/// One word of a disassembly listing
+pub struct DisasmLine {
+    pub addr: u32,
+    pub raw: u64,
+    pub text: String,
+}
+
+impl fmt::Display for DisasmLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:>6}:", self.addr)?;
+        for byte in self.raw.to_le_bytes().iter() {
+            write!(f, " {:02x}", byte)?;
+        }
+        write!(f, "  {}", self.text)
+    }
+}
+
+/**
+ * Turns a single word back into assembly, using the same operand layout
+ * `CPU::execute` reads. Returns `None` when the word can't be an instruction:
+ * unknown opcode, a register byte past r7 or stray bytes outside the operands.
+ */
+pub fn disassemble_word(word: u64) -> Option<String> {
+    let bytes = word.to_le_bytes();
+    let instr: crate::Instruction = num::FromPrimitive::from_u8(bytes[0])?;
+
+    let mut used = [false; 8];
+    used[0] = true;
+    let mut text = String::from(instr.mnemonic());
+
+    for operand in instr.operands() {
+        match *operand {
+            Operand::Reg(idx) => {
+                if bytes[idx] >= 8 {
+                    return None;
+                }
+                used[idx] = true;
+                text.push_str(&format!(" r{}", bytes[idx]));
+            }
+            Operand::Addr(idx) | Operand::Target(idx) | Operand::Imm(idx) => {
+                used[idx..idx + 4].iter_mut().for_each(|u| *u = true);
+                text.push_str(&format!(" {}", deserialize_u32_array(idx, &bytes)));
+            }
+            Operand::SignedImm(idx) => {
+                used[idx..idx + 4].iter_mut().for_each(|u| *u = true);
+                text.push_str(&format!(" {}", deserialize_u32_array(idx, &bytes) as i32));
+            }
+        }
+    }
+
+    if bytes
+        .iter()
+        .zip(used.iter())
+        .any(|(byte, used)| *byte != 0 && !used)
+    {
+        return None;
+    }
+
+    Some(text)
+}
+
+/// Disassembles `words` as if they were loaded starting at `base`, leaving
+/// off any that would land past the end of the address space
+pub fn disassemble(words: &[u64], base: u32) -> Vec<DisasmLine> {
+    (base..=u32::MAX)
+        .zip(words)
+        .map(|(addr, &raw)| DisasmLine {
+            addr,
+            raw,
+            text: disassemble_word(raw).unwrap_or_else(|| format!(".word {}", raw)),
+        })
+        .collect()
+}
+
+/// Disassembles the words of `memory` in `range`, stopping at its end
+pub fn disassemble_memory<M: MemoryBackend>(memory: &M, range: Range<u32>) -> Vec<DisasmLine> {
+    let words: Vec<u64> = range
+        .clone()
+        .map_while(|addr| memory.read(addr).ok())
+        .collect();
+    disassemble(&words, range.start)
+}
+
+/// Lists `words` as plain `.word` data, for sections known not to hold
+/// code, stopping at the end of the address space like `disassemble`
+pub fn data_listing(words: &[u64], base: u32) -> Vec<DisasmLine> {
+    (base..=u32::MAX)
+        .zip(words)
+        .map(|(addr, &raw)| DisasmLine {
+            addr,
+            raw,
+            text: format!(".word {}", raw),
+        })
+        .collect()
+}
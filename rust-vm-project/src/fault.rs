@@ -0,0 +1,93 @@
+use std::fmt;
+
+/// Everything that can go wrong while the CPU is executing an instruction
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VmFault {
+    InvalidOpcode(u8),       // the opcode byte doesn't map to an `Instruction`
+    BadRegister(u8),         // register index past r7
+    MemoryOutOfRange(u32),   // address past the end of memory
+    ArithmeticOverflow,      // the result doesn't fit in a u64
+    DivideByZero,            // `div` or `mod` with a zero divisor
+    StackOverflow(u32),      // push or call with the stack full, holds the stack pointer
+    StackUnderflow(u32),     // pop or ret with the stack empty, holds the stack pointer
+    Protection(u32, Access), // the address's region doesn't allow that kind of access
+    Misaligned(u64),         // halfword or word access at a byte address it doesn't divide
+    UnknownHostCall(u32),    // `hostcall` with an id nothing is registered as
+}
+
+/// The ways an instruction can touch memory, for `VmFault::Protection`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute, // fetching the next instruction
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+            Access::Execute => write!(f, "execute"),
+        }
+    }
+}
+
+impl fmt::Display for VmFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmFault::InvalidOpcode(op) => write!(f, "invalid opcode {}", op),
+            VmFault::BadRegister(reg) => write!(f, "bad register r{}", reg),
+            VmFault::MemoryOutOfRange(addr) => write!(f, "memory address {} out of range", addr),
+            VmFault::ArithmeticOverflow => write!(f, "arithmetic overflow"),
+            VmFault::DivideByZero => write!(f, "division by zero"),
+            VmFault::StackOverflow(sp) => write!(f, "stack overflow, sp {}", sp),
+            VmFault::StackUnderflow(sp) => write!(f, "stack underflow, sp {}", sp),
+            VmFault::UnknownHostCall(id) => write!(f, "no host call registered as {}", id),
+            VmFault::Misaligned(addr) => write!(f, "misaligned access at byte address {}", addr),
+            VmFault::Protection(addr, access) => {
+                write!(f, "protection fault, {} of address {}", access, addr)
+            }
+        }
+    }
+}
+
+impl VmFault {
+    /// Slot in the vector table (see `interrupt::InterruptController`) for
+    /// this kind of fault. Bad addresses, out of range or misaligned, share
+    /// one, and unknown host calls count as invalid instructions.
+    pub fn vector(&self) -> u32 {
+        match self {
+            VmFault::InvalidOpcode(_) | VmFault::UnknownHostCall(_) => 0,
+            VmFault::BadRegister(_) => 1,
+            VmFault::MemoryOutOfRange(_) | VmFault::Misaligned(_) => 2,
+            VmFault::ArithmeticOverflow => 3,
+            VmFault::DivideByZero => 4,
+            VmFault::StackOverflow(_) => 5,
+            VmFault::StackUnderflow(_) => 6,
+            VmFault::Protection(..) => 7,
+        }
+    }
+}
+
+impl std::error::Error for VmFault {}
+
+/// A `VmFault` along with the program counter and instruction that raised it
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Fault {
+    pub kind: VmFault,
+    pub pc: u32,
+    pub instruction: [u8; 8],
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[DEATH]: {} at pc {} executing {:?}",
+            self.kind, self.pc, self.instruction
+        )
+    }
+}
+
+impl std::error::Error for Fault {}
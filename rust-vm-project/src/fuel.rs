@@ -0,0 +1,65 @@
+use crate::Instruction;
+
+/**
+ * What each opcode costs out of a `CPU::run_with_budget` budget. Everything
+ * costs 1 by default, so a budget is a plain instruction count until some
+ * costs are changed.
+ *
+ * ```
+ * # use rust_vm_project::{CostTable, Instruction};
+ * let costs = CostTable::default()
+ *     .with(Instruction::Mul, 3)
+ *     .with(Instruction::Div, 20);
+ * assert_eq!(costs.cost(Instruction::Div), 20);
+ * ```
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CostTable {
+    costs: [u64; 256], // indexed by opcode byte
+}
+
+impl CostTable {
+    /// Every opcode costs `cost`
+    pub fn uniform(cost: u64) -> Self {
+        Self { costs: [cost; 256] }
+    }
+
+    pub fn with(mut self, instruction: Instruction, cost: u64) -> Self {
+        self.set(instruction, cost);
+        self
+    }
+
+    pub fn set(&mut self, instruction: Instruction, cost: u64) {
+        self.costs[instruction as usize] = cost;
+    }
+
+    pub fn cost(&self, instruction: Instruction) -> u64 {
+        self.costs[instruction as usize]
+    }
+
+    /// Cost by raw opcode byte, what the CPU charges after a fetch
+    pub(crate) fn opcode_cost(&self, opcode: u8) -> u64 {
+        self.costs[opcode as usize]
+    }
+}
+
+impl Default for CostTable {
+    fn default() -> Self {
+        Self::uniform(1)
+    }
+}
+
+/// How a `CPU::run_with_budget` ended when it didn't fault
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RunOutcome {
+    Exited { cycles: u64 },    // the program exited, having used `cycles`
+    OutOfFuel { cycles: u64 }, // the budget ran out, `cycles` is at least the budget
+}
+
+impl RunOutcome {
+    pub fn cycles(&self) -> u64 {
+        match self {
+            RunOutcome::Exited { cycles } | RunOutcome::OutOfFuel { cycles } => *cycles,
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use crate::fault::VmFault;
+use crate::{MemoryBackend, CPU};
+
+/**
+ * A host function VM programs reach with `hostcall <id>`. It gets the whole
+ * machine, so it can read arguments from registers or memory and leave its
+ * results there. An error faults the `hostcall` like any other instruction,
+ * though whatever the function changed before failing stays changed.
+ */
+pub type HostFn<M> = Box<dyn FnMut(&mut CPU<M>) -> Result<(), VmFault>>;
+
+impl<M: MemoryBackend> CPU<M> {
+    /// Registers `f` as host call `id`, replacing anything registered before
+    pub fn register_hostcall(
+        &mut self,
+        id: u32,
+        f: impl FnMut(&mut CPU<M>) -> Result<(), VmFault> + 'static,
+    ) {
+        self.host_calls_mut().insert(id, Box::new(f));
+    }
+
+    /// Removes host call `id`, false if there wasn't one
+    pub fn unregister_hostcall(&mut self, id: u32) -> bool {
+        self.host_calls_mut().remove(&id).is_some()
+    }
+
+    pub fn has_hostcall(&self, id: u32) -> bool {
+        self.host_calls().contains_key(&id)
+    }
+
+    /// Runs host call `id`. It's out of the registry while it runs, so a
+    /// host function that steps the machine into calling itself again gets
+    /// `UnknownHostCall`.
+    pub(crate) fn call_host(&mut self, id: u32) -> Result<(), VmFault> {
+        let mut f = self
+            .host_calls_mut()
+            .remove(&id)
+            .ok_or(VmFault::UnknownHostCall(id))?;
+        let out = f(self);
+        // unless it registered a replacement for itself
+        self.host_calls_mut().entry(id).or_insert(f);
+        out
+    }
+}
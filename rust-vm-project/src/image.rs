@@ -0,0 +1,271 @@
+use crate::codec::{put_u16, put_u32, put_u64, Reader};
+use crate::{MachineBuilder, MemoryBackend, Permissions, CPU};
+use anyhow::{anyhow, Result};
+use std::path::Path;
+
+pub const MAGIC: [u8; 4] = *b"RVMI";
+
+/// Bumped whenever opcodes are added, images from newer ISAs are refused
+pub const ISA_VERSION: u16 = 9;
+
+/**
+ * An executable on disk. Everything is little endian:
+ *
+ * ```text
+ * magic       4 bytes, "RVMI"
+ * isa version u16
+ * reserved    u16, zero
+ * entry       u32
+ * code        base u32, word count u32, words u64 * count
+ * data        base u32, word count u32, words u64 * count
+ * symbols     count u32, then per symbol addr u32, name length u16, utf8 name
+ * ```
+ *
+ * Sections have to end inside the 32 bit address space, images that don't
+ * are refused when they're written or read.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgramImage {
+    pub isa_version: u16,
+    pub entry: u32,
+    pub code: Section,
+    pub data: Section,
+    pub symbols: Vec<Symbol>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Section {
+    pub base: u32,
+    pub words: Vec<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub addr: u32,
+}
+
+impl Section {
+    /// One past the last address, as a u64 so it can't overflow
+    pub fn end(&self) -> u64 {
+        self.base as u64 + self.words.len() as u64
+    }
+
+    pub fn contains(&self, addr: u32) -> bool {
+        (self.base as u64..self.end()).contains(&(addr as u64))
+    }
+
+    /// The word at memory address `addr`, if it's in this section
+    pub fn word(&self, addr: u32) -> Option<u64> {
+        let offset = addr.checked_sub(self.base)?;
+        self.words.get(offset as usize).copied()
+    }
+}
+
+impl ProgramImage {
+    pub fn new(entry: u32, code: Section, data: Section) -> Self {
+        Self {
+            isa_version: ISA_VERSION,
+            entry,
+            code,
+            data,
+            symbols: Vec::new(),
+        }
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<u32> {
+        self.symbols.iter().find(|s| s.name == name).map(|s| s.addr)
+    }
+
+    /// Fails on anything `from_bytes` would refuse: a section past the end
+    /// of the address space or a symbol name too long for its u16 length
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&MAGIC);
+        put_u16(&mut out, self.isa_version);
+        put_u16(&mut out, 0);
+        put_u32(&mut out, self.entry);
+
+        for (name, section) in [("code", &self.code), ("data", &self.data)] {
+            if section.end() > 1 << 32 {
+                return Err(anyhow!(
+                    "{} section of {} words at {} runs past the end of the address space",
+                    name,
+                    section.words.len(),
+                    section.base
+                ));
+            }
+            put_u32(&mut out, section.base);
+            put_u32(&mut out, section.words.len() as u32);
+            for word in &section.words {
+                put_u64(&mut out, *word);
+            }
+        }
+
+        put_u32(&mut out, self.symbols.len() as u32);
+        for symbol in &self.symbols {
+            let len = u16::try_from(symbol.name.len()).map_err(|_| {
+                anyhow!(
+                    "symbol name is {} bytes, at most 65535 fit",
+                    symbol.name.len()
+                )
+            })?;
+            put_u32(&mut out, symbol.addr);
+            put_u16(&mut out, len);
+            out.extend_from_slice(symbol.name.as_bytes());
+        }
+
+        Ok(out)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(bytes);
+
+        if reader.take(4)? != MAGIC {
+            return Err(anyhow!("not a program image, bad magic number"));
+        }
+        let isa_version = reader.u16()?;
+        if isa_version > ISA_VERSION {
+            return Err(anyhow!(
+                "image needs isa version {}, this machine supports up to {}",
+                isa_version,
+                ISA_VERSION
+            ));
+        }
+        reader.u16()?;
+        let entry = reader.u32()?;
+
+        let mut sections = [Section::default(), Section::default()];
+        for (name, section) in ["code", "data"].iter().zip(sections.iter_mut()) {
+            section.base = reader.u32()?;
+            let count = reader.u32()?;
+            if section.base as u64 + count as u64 > 1 << 32 {
+                return Err(anyhow!(
+                    "{} section of {} words at {} runs past the end of the address space",
+                    name,
+                    count,
+                    section.base
+                ));
+            }
+            section.words = (0..count).map(|_| reader.u64()).collect::<Result<_>>()?;
+        }
+        let [code, data] = sections;
+
+        let count = reader.u32()?;
+        let mut symbols = Vec::new();
+        for _ in 0..count {
+            let addr = reader.u32()?;
+            let len = reader.u16()?;
+            let name = String::from_utf8(reader.take(len as usize)?.to_vec())
+                .map_err(|_| anyhow!("symbol name is not utf8"))?;
+            symbols.push(Symbol { name, addr });
+        }
+
+        if !reader.is_empty() {
+            return Err(anyhow!("trailing bytes after the symbol table"));
+        }
+
+        Ok(Self {
+            isa_version,
+            entry,
+            code,
+            data,
+            symbols,
+        })
+    }
+
+    pub fn read_from(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        Self::from_bytes(&bytes).map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+
+    pub fn write_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_bytes()?).map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+
+    /// Checks the image makes sense for a machine with `memory_words` of memory
+    pub fn validate(&self, memory_words: u64) -> Result<()> {
+        if self.code.words.is_empty() {
+            return Err(anyhow!("image has no code"));
+        }
+        if !self.code.contains(self.entry) {
+            return Err(anyhow!(
+                "entry point {} is outside the code section {}..{}",
+                self.entry,
+                self.code.base,
+                self.code.end()
+            ));
+        }
+        for (name, section) in [("code", &self.code), ("data", &self.data)] {
+            if section.end() > memory_words {
+                return Err(anyhow!(
+                    "{} section {}..{} doesn't fit in {} words of memory",
+                    name,
+                    section.base,
+                    section.end(),
+                    memory_words
+                ));
+            }
+        }
+        if !self.data.words.is_empty()
+            && (self.code.base as u64) < self.data.end()
+            && (self.data.base as u64) < self.code.end()
+        {
+            return Err(anyhow!("code and data sections overlap"));
+        }
+        Ok(())
+    }
+
+    /// Validates the image and brings up a machine on `memory` ready to run it
+    pub fn load<M: MemoryBackend>(&self, memory: M) -> Result<CPU<M>> {
+        self.validate(memory.words())?;
+        let cpu = MachineBuilder::new(memory).image(self).build()?;
+
+        let stack = cpu.stack_region();
+        for section in [&self.code, &self.data] {
+            if !section.words.is_empty()
+                && (section.base as u64) < stack.end as u64
+                && (stack.start as u64) < section.end()
+            {
+                return Err(anyhow!(
+                    "section {}..{} runs into the stack at {}..{}",
+                    section.base,
+                    section.end(),
+                    stack.start,
+                    stack.end
+                ));
+            }
+        }
+        Ok(cpu)
+    }
+}
+
+impl<M: MemoryBackend> MachineBuilder<M> {
+    /// Loads both sections of `image` and starts at its entry point. A
+    /// `vectors` label becomes the vector table. The code section is read
+    /// only and the only place that can be executed, the data section and
+    /// everything else can be read and written.
+    pub fn image(self, image: &ProgramImage) -> Self {
+        let builder = self
+            .program(&image.code.words, image.code.base as usize)
+            .program(&image.data.words, image.data.base as usize)
+            .protect(
+                image.code.base,
+                image.code.words.len() as u32,
+                Permissions::RX,
+            )
+            .protect(
+                image.data.base,
+                image.data.words.len() as u32,
+                Permissions::RW,
+            )
+            .default_permissions(Permissions::RW)
+            .entry(image.entry);
+        match image.symbol("vectors") {
+            Some(base) => builder.vectors(base),
+            None => builder,
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use crate::fault::VmFault;
+
+/**
+ * Arithmetic treats registers as unsigned u64s. `add`, `sub`, `mul` and
+ * `icrr` fault with `ArithmeticOverflow` instead of wrapping, `div` and
+ * `mod` fault with `DivideByZero`. Shifts are logical and shifting by 64 or
+ * more bits leaves zero, rotates take their amount mod 64. A faulting
+ * instruction leaves its destination register untouched. Every ALU
+ * instruction and `cmp` set the `Flags` the conditional branches test.
+ *
+ * Interrupts and faults with a handler in the vector table (see
+ * `interrupt::InterruptController`) push the address to resume at and then
+ * a status word, `Flags::bits` plus bit 4 for interrupts enabled, disable
+ * interrupts and jump to the handler. `iret` undoes all of that. A fault
+ * resumes at the instruction after the one that faulted.
+ *
+ * The register addressed loads and stores fault with `MemoryOutOfRange`
+ * (holding the low 32 bits) when the address doesn't fit in a u32. The post
+ * increment forms bump the address register after the access, for `ldp` the
+ * loaded value wins if the two registers are the same.
+ *
+ * The byte, halfword (16 bit) and word (32 bit) loads and stores take byte
+ * addresses instead: byte `a` is byte `a % 8` of memory word `a / 8`,
+ * counting from the least significant end, the same little endian order
+ * instructions are encoded in. Halfwords and words have to be aligned to
+ * their size, anything else faults with `Misaligned`. Stores leave the rest
+ * of the memory word alone.
+ */
+#[derive(FromPrimitive, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    Exit,              // ext - the computer does nothing, it just dies
+    LoadFromMem,       // lod <mem_address> <register>
+    WriteToMem,        // wrt <register> <mem_address>
+    Add,               // add <reg1> <reg2> <reg3> - adds reg1 to reg2 and writes to reg3
+    Sub,               // sub <reg1> <reg2> <reg3> - subs reg2 from reg1 and writes to reg3
+    SetProgramCounter, // spc <u32_value> - sets the program counter to the u32 in the instruction
+    ClearAllRegisters, // clra - sets every register to zero
+    ClearRegister,     // clr <reg_addr> - sets this register to zero
+    RegisterWrite,     // rw <reg1> <reg2> - writes the value of register 1 to register 2
+    IfEqSPCElsePass,   // ieqe <reg1> <reg2> <u32_program_counter>
+    IncrementReg,      // icrr <reg> - adds one to the register
+    Mul,               // mul <reg1> <reg2> <reg3> - reg3 = reg1 * reg2
+    Div,               // div <reg1> <reg2> <reg3> - reg3 = reg1 / reg2, rounding down
+    Mod,               // mod <reg1> <reg2> <reg3> - reg3 = reg1 % reg2
+    And,               // and <reg1> <reg2> <reg3> - reg3 = reg1 & reg2
+    Or,                // or <reg1> <reg2> <reg3> - reg3 = reg1 | reg2
+    Xor,               // xor <reg1> <reg2> <reg3> - reg3 = reg1 ^ reg2
+    Not,               // not <reg1> <reg3> - reg3 = !reg1
+    ShiftLeft,         // shl <reg1> <reg2> <reg3> - reg3 = reg1 << reg2
+    ShiftRight,        // shr <reg1> <reg2> <reg3> - reg3 = reg1 >> reg2
+    RotateLeft,        // rol <reg1> <reg2> <reg3> - reg3 = reg1 rotated left by reg2
+    RotateRight,       // ror <reg1> <reg2> <reg3> - reg3 = reg1 rotated right by reg2
+    Compare,           // cmp <reg1> <reg2> - sets the flags from reg1 - reg2, writes nothing
+    BranchEq,          // beq <u32_program_counter> - jump if equal
+    BranchNe,          // bne <u32_program_counter> - jump if not equal
+    BranchLt,          // blt <u32_program_counter> - jump if less, signed
+    BranchLe,          // ble <u32_program_counter> - jump if less or equal, signed
+    BranchGt,          // bgt <u32_program_counter> - jump if greater, signed
+    BranchGe,          // bge <u32_program_counter> - jump if greater or equal, signed
+    BranchLtU,         // bltu <u32_program_counter> - jump if less, unsigned
+    BranchLeU,         // bleu <u32_program_counter> - jump if less or equal, unsigned
+    BranchGtU,         // bgtu <u32_program_counter> - jump if greater, unsigned
+    BranchGeU,         // bgeu <u32_program_counter> - jump if greater or equal, unsigned
+    Push,              // push <reg> - pushes the register onto the stack
+    Pop,               // pop <reg> - pops the top of the stack into the register
+    Call,              // call <u32_program_counter> - pushes the return address and jumps
+    Return,            // ret - pops the return address into the program counter
+    InterruptReturn,   // iret - pops the status word and return address pushed on entry
+    EnableInterrupts,  // ei - lets pending interrupts be delivered
+    DisableInterrupts, // di - holds interrupts pending until the next ei or iret
+    LoadImmediate,     // li <reg> <imm32> - sets the register to the u32 in the instruction
+    LoadSignedImm,     // lis <reg> <imm32> - like li but the i32 is sign extended
+    LoadIndirect,      // ldr <addr_reg> <reg> - loads the word at the address in addr_reg
+    LoadOffset,        // ldo <base_reg> <i32_offset> <reg> - loads the word at base_reg + offset
+    LoadPostInc,       // ldp <addr_reg> <reg> - like ldr, then adds one to addr_reg
+    StoreIndirect,     // str <reg> <addr_reg> - stores reg at the address in addr_reg
+    StoreOffset,       // sto <reg> <base_reg> <i32_offset> - stores reg at base_reg + offset
+    StorePostInc,      // stp <reg> <addr_reg> - like str, then adds one to addr_reg
+    LoadByte,          // lb <base_reg> <i32_offset> <reg> - byte at base + offset, sign extended
+    LoadByteU,         // lbu <base_reg> <i32_offset> <reg> - same, zero extended
+    LoadHalf,          // lh <base_reg> <i32_offset> <reg> - loads a 16 bit halfword, sign extended
+    LoadHalfU,         // lhu <base_reg> <i32_offset> <reg> - same, zero extended
+    LoadWord,          // lw <base_reg> <i32_offset> <reg> - loads a 32 bit word, sign extended
+    LoadWordU,         // lwu <base_reg> <i32_offset> <reg> - same, zero extended
+    StoreByte,         // sb <reg> <base_reg> <i32_offset> - stores the low byte of reg
+    StoreHalf,         // sh <reg> <base_reg> <i32_offset> - stores the low 16 bits of reg
+    StoreWord,         // sw <reg> <base_reg> <i32_offset> - stores the low 32 bits of reg
+    HostCall,          // hostcall <u32_id> - runs the function the host registered as id
+}
+
+/// Where an operand lives inside the encoded instruction, as a byte offset
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Reg(usize),       // one byte register index
+    Addr(usize),      // u32 memory address
+    Target(usize),    // u32 program counter value
+    Imm(usize),       // u32 constant, zero extended
+    SignedImm(usize), // i32 constant, sign extended
+}
+
+impl Instruction {
+    /// The assembly mnemonic, as written in the comments above
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Instruction::Exit => "ext",
+            Instruction::LoadFromMem => "lod",
+            Instruction::WriteToMem => "wrt",
+            Instruction::Add => "add",
+            Instruction::Sub => "sub",
+            Instruction::SetProgramCounter => "spc",
+            Instruction::ClearAllRegisters => "clra",
+            Instruction::ClearRegister => "clr",
+            Instruction::RegisterWrite => "rw",
+            Instruction::IfEqSPCElsePass => "ieqe",
+            Instruction::IncrementReg => "icrr",
+            Instruction::Mul => "mul",
+            Instruction::Div => "div",
+            Instruction::Mod => "mod",
+            Instruction::And => "and",
+            Instruction::Or => "or",
+            Instruction::Xor => "xor",
+            Instruction::Not => "not",
+            Instruction::ShiftLeft => "shl",
+            Instruction::ShiftRight => "shr",
+            Instruction::RotateLeft => "rol",
+            Instruction::RotateRight => "ror",
+            Instruction::Compare => "cmp",
+            Instruction::BranchEq => "beq",
+            Instruction::BranchNe => "bne",
+            Instruction::BranchLt => "blt",
+            Instruction::BranchLe => "ble",
+            Instruction::BranchGt => "bgt",
+            Instruction::BranchGe => "bge",
+            Instruction::BranchLtU => "bltu",
+            Instruction::BranchLeU => "bleu",
+            Instruction::BranchGtU => "bgtu",
+            Instruction::BranchGeU => "bgeu",
+            Instruction::Push => "push",
+            Instruction::Pop => "pop",
+            Instruction::Call => "call",
+            Instruction::Return => "ret",
+            Instruction::InterruptReturn => "iret",
+            Instruction::EnableInterrupts => "ei",
+            Instruction::DisableInterrupts => "di",
+            Instruction::LoadImmediate => "li",
+            Instruction::LoadSignedImm => "lis",
+            Instruction::LoadIndirect => "ldr",
+            Instruction::LoadOffset => "ldo",
+            Instruction::LoadPostInc => "ldp",
+            Instruction::StoreIndirect => "str",
+            Instruction::StoreOffset => "sto",
+            Instruction::StorePostInc => "stp",
+            Instruction::LoadByte => "lb",
+            Instruction::LoadByteU => "lbu",
+            Instruction::LoadHalf => "lh",
+            Instruction::LoadHalfU => "lhu",
+            Instruction::LoadWord => "lw",
+            Instruction::LoadWordU => "lwu",
+            Instruction::StoreByte => "sb",
+            Instruction::StoreHalf => "sh",
+            Instruction::StoreWord => "sw",
+            Instruction::HostCall => "hostcall",
+        }
+    }
+
+    /// Operand layout, in the order they are written in assembly
+    pub fn operands(&self) -> &'static [Operand] {
+        use Operand::*;
+        match self {
+            Instruction::Exit
+            | Instruction::ClearAllRegisters
+            | Instruction::Return
+            | Instruction::InterruptReturn
+            | Instruction::EnableInterrupts
+            | Instruction::DisableInterrupts => &[],
+            Instruction::LoadFromMem => &[Addr(1), Reg(5)],
+            Instruction::WriteToMem => &[Reg(1), Addr(2)],
+            Instruction::Add
+            | Instruction::Sub
+            | Instruction::Mul
+            | Instruction::Div
+            | Instruction::Mod
+            | Instruction::And
+            | Instruction::Or
+            | Instruction::Xor
+            | Instruction::ShiftLeft
+            | Instruction::ShiftRight
+            | Instruction::RotateLeft
+            | Instruction::RotateRight => &[Reg(1), Reg(2), Reg(3)],
+            Instruction::Not => &[Reg(1), Reg(3)],
+            Instruction::SetProgramCounter
+            | Instruction::BranchEq
+            | Instruction::BranchNe
+            | Instruction::BranchLt
+            | Instruction::BranchLe
+            | Instruction::BranchGt
+            | Instruction::BranchGe
+            | Instruction::BranchLtU
+            | Instruction::BranchLeU
+            | Instruction::BranchGtU
+            | Instruction::BranchGeU
+            | Instruction::Call => &[Target(1)],
+            Instruction::HostCall => &[Imm(1)],
+            Instruction::ClearRegister
+            | Instruction::IncrementReg
+            | Instruction::Push
+            | Instruction::Pop => &[Reg(1)],
+            Instruction::RegisterWrite
+            | Instruction::Compare
+            | Instruction::LoadIndirect
+            | Instruction::LoadPostInc
+            | Instruction::StoreIndirect
+            | Instruction::StorePostInc => &[Reg(1), Reg(2)],
+            Instruction::LoadOffset
+            | Instruction::LoadByte
+            | Instruction::LoadByteU
+            | Instruction::LoadHalf
+            | Instruction::LoadHalfU
+            | Instruction::LoadWord
+            | Instruction::LoadWordU => &[Reg(1), SignedImm(2), Reg(6)],
+            Instruction::StoreOffset
+            | Instruction::StoreByte
+            | Instruction::StoreHalf
+            | Instruction::StoreWord => &[Reg(1), Reg(2), SignedImm(3)],
+            Instruction::IfEqSPCElsePass => &[Reg(1), Reg(2), Target(3)],
+            Instruction::LoadImmediate => &[Reg(1), Imm(2)],
+            Instruction::LoadSignedImm => &[Reg(1), SignedImm(2)],
+        }
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Instruction> {
+        (0..=u8::MAX)
+            .filter_map(<Instruction as num::FromPrimitive>::from_u8)
+            .find(|instr| instr.mnemonic() == mnemonic)
+    }
+}
+
+/// Generate Instruction
+pub fn incode_instr(input: [u8; 8]) -> u64 {
+    u64::from_le_bytes(input)
+}
+
+pub fn deserialize_instruction(val: u8) -> Result<Instruction, VmFault> {
+    let out: Option<Instruction> = num::FromPrimitive::from_u8(val);
+    out.ok_or(VmFault::InvalidOpcode(val))
+}
+
+pub fn deserialize_u32_array(idx: usize, ray: &[u8; 8]) -> u32 {
+    u32::from_le_bytes([ray[idx], ray[idx + 1], ray[idx + 2], ray[idx + 3]])
+}
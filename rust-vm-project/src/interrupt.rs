@@ -0,0 +1,77 @@
+/// Vector table slot for IRQ line 0, the slots below it belong to faults
+pub const IRQ_VECTOR: u32 = 8;
+/// Number of IRQ lines
+pub const IRQ_LINES: u8 = 16;
+/// Words in a vector table
+pub const VECTORS: u32 = IRQ_VECTOR + IRQ_LINES as u32;
+
+/**
+ * Pending IRQ lines plus the global enable the CPU checks before every
+ * instruction. Nothing is ever delivered until a vector table has been set,
+ * and lines stay pending while interrupts are disabled.
+ *
+ * A vector table is `VECTORS` words of memory, slot `VmFault::vector` for
+ * each fault and `IRQ_VECTOR + line` for each IRQ line, each holding the
+ * address of its handler. A zero slot means no handler: faults go to the host
+ * as usual and IRQs on that line are dropped.
+ */
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct InterruptController {
+    pending: u16,
+    enabled: bool,
+    vectors: Option<u32>,
+}
+
+impl InterruptController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks a line pending, lines past `IRQ_LINES` are ignored
+    pub fn raise(&mut self, line: u8) {
+        if line < IRQ_LINES {
+            self.pending |= 1 << line;
+        }
+    }
+
+    /// Bit n set means line n is pending
+    pub fn pending(&self) -> u16 {
+        self.pending
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Address of the vector table, if there is one
+    pub fn vectors(&self) -> Option<u32> {
+        self.vectors
+    }
+
+    pub fn set_vectors(&mut self, base: Option<u32>) {
+        self.vectors = base;
+    }
+
+    /// Replaces the pending lines wholesale, for restoring snapshots
+    pub fn set_pending(&mut self, mask: u16) {
+        self.pending = mask;
+    }
+
+    pub(crate) fn raise_mask(&mut self, mask: u16) {
+        self.pending |= mask;
+    }
+
+    /// The lowest pending line, cleared, if one can be delivered right now
+    pub(crate) fn take(&mut self) -> Option<u8> {
+        if !self.enabled || self.vectors.is_none() || self.pending == 0 {
+            return None;
+        }
+        let line = self.pending.trailing_zeros() as u8;
+        self.pending &= !(1 << line);
+        Some(line)
+    }
+}
@@ -0,0 +1,56 @@
+//! A little 64 bit register machine.
+//!
+//! Eight u64 registers, a u32 program counter and word addressed memory.
+//! Every instruction is a single little endian u64, the first byte is the
+//! `Instruction` and the rest are its operands (see `Instruction::operands`).
+//!
+//! ```
+//! # use rust_vm_project::{asm, FlatMemory, CPU};
+//! # fn main() -> anyhow::Result<()> {
+//! let words = asm::assemble("ext")?;
+//! let mut cpu = CPU::builder(FlatMemory::new(100))
+//!     .program(&words, 0)
+//!     .build()?;
+//! cpu.run()?;
+//! # Ok(())
+//! # }
+//! ```
+extern crate num;
+#[macro_use]
+extern crate num_derive;
+
+pub mod asm;
+pub mod cfg;
+pub mod debugger;
+pub mod device;
+pub mod disasm;
+pub mod fault;
+pub mod image;
+pub mod interrupt;
+pub mod profile;
+pub mod trace;
+pub mod undo;
+pub mod verify;
+
+mod alu;
+mod codec;
+mod cpu;
+mod decode;
+mod fuel;
+mod host;
+mod instruction;
+mod memory;
+mod snapshot;
+
+pub use alu::Flags;
+pub use cpu::{MachineBuilder, CPU};
+pub use fault::{Access, Fault, VmFault};
+pub use fuel::{CostTable, RunOutcome};
+pub use host::HostFn;
+pub use instruction::{
+    deserialize_instruction, deserialize_u32_array, incode_instr, Instruction, Operand,
+};
+pub use memory::{
+    FlatMemory, MemoryBackend, MemoryController, MemoryWrite, PagedMemory, Permissions, PAGE_WORDS,
+};
+pub use snapshot::SNAPSHOT_VERSION;
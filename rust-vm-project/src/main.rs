@@ -1,390 +1,402 @@
-extern crate num;
-#[macro_use]
-extern crate num_derive;
-use anyhow::{anyhow, Result};
-
 #[macro_use]
 extern crate timeit;
 
-#[derive(FromPrimitive, Debug, Copy, Clone)]
-enum Instruction {
-    Exit,              // ext - the computer does nothing, it just dies
-    LoadFromMem,       // lod <mem_address> <register>
-    WriteToMem,        // wrt <register> <mem_address>
-    Add,               // add <reg1> <reg2> <reg3> - adds reg1 to reg2 and writes to reg3
-    Sub,               // sub <reg1> <reg2> <reg3> - subs reg1 to reg2 and writes to reg3
-    SetProgramCounter, // spc <u32_value> - sets the program counter to the u32 in the instruction
-    ClearAllRegisters, // clra - sets every register to zero
-    ClearRegister,     // clr <reg_addr> - sets this register to zero
-    RegisterWrite,     // rw <reg1> <reg2> - writes the value of register 1 to register 2
-    IfEqSPCElsePass,   // ieqe <reg1> <reg2> <u32_program_counter>
-    IncrementReg,      // icrr <reg> - adds one to the register
-}
+use anyhow::{anyhow, Result};
+use rust_vm_project::cfg::Cfg;
+use rust_vm_project::debugger::Debugger;
+use rust_vm_project::device::{self, ConsoleIn, Timer};
+use rust_vm_project::image::ProgramImage;
+use rust_vm_project::profile::Profile;
+use rust_vm_project::trace::{self, TraceFilter, TraceFormat, Tracer};
+use rust_vm_project::verify;
+use rust_vm_project::{asm, disasm, FlatMemory, MemoryBackend, PagedMemory, RunOutcome, CPU};
+
+const USAGE: &str = "usage:
+    rust-vm-project                          run the fib demo
+    rust-vm-project asm <source> <image>     assemble source into a program image
+    rust-vm-project disasm <image>           list a program image
+    rust-vm-project verify <image>           check a program image without running it
+    rust-vm-project cfg <image> [dot]        list a program image's basic blocks, loops and
+                                             unreachable code, writing a Graphviz graph to dot
+    rust-vm-project run [--debug] <image>    run a program image
+    rust-vm-project run --budget <n> <image> run a program image for at most n cycles
+    rust-vm-project run --profile <image>    run a program image and print where it spent its time
+    rust-vm-project run --folded <out> <image>
+                                             run a program image and write folded call stacks to out
+    rust-vm-project debug <image> [script]   debug a program image, commands from script if given
+    rust-vm-project resume <image> <snapshot>
+                                             carry on running a snapshot the debugger saved of image
+    rust-vm-project trace [--binary] <image> <out>
+                                             run a program image tracing every instruction to out, - for stdout
+    rust-vm-project trace-filter <trace> [--pc <lo>..<hi>] [--reg <rN>] [--addr <addr>]
+                                             print the records of a trace that match, as JSON lines
+    rust-vm-project trace-diff <trace> <trace>
+                                             find the first record where two traces disagree
+    rust-vm-project bench [image]            time a program image, or a built in loop, with and
+                                             without the decoded instruction cache";
 
-/// Generate Instruction
-fn incode_instr(input: [u8; 8]) -> u64 {
-    u64::from_le_bytes(input)
-}
+fn fib_n(n: usize) -> u64 {
+    let mut a = 0_u64;
+    let mut b = 1_u64;
 
-fn deserialize_instruction(val: u8) -> Result<Instruction> {
-    let out: Option<Instruction> = num::FromPrimitive::from_u8(val);
-    if let Some(x) = out {
-        Ok(x)
-    } else {
-        Err(anyhow!(
-            "[DEATH]: FAILED TO DESERIALIZE INSTRUCTION, {} PROVIDED",
-            val
-        ))
+    for _ in 0..n {
+        let tmp = b + a;
+        a = b;
+        b = tmp;
     }
-}
-
-fn deserialize_u32_array(idx: usize, ray: &[u8; 8]) -> u32 {
-    u32::from_le_bytes([ray[idx], ray[idx + 1], ray[idx + 2], ray[idx + 3]])
-}
 
-struct MemoryController<'b, const N: usize> {
-    memory: &'b mut Memory<N>,
+    b
 }
 
-impl<'b, const N: usize> MemoryController<'b, N> {
-    fn new_from(input: &'b mut Memory<N>) -> Self {
-        Self { memory: input }
-    }
-
-    fn load_program_external(&mut self, ext_prg: &Vec<u64>, idx: usize) {
-        for i in 0..ext_prg.len() {
-            self.write((i + idx) as u32, ext_prg[i]);
-        }
-    }
-
-    fn read(&self, idx: u32) -> u64 {
-        self.memory.data[idx as usize].clone()
-    }
+// we want everything to be little endian
 
-    fn write(&mut self, idx: u32, val: u64) {
-        self.memory.data[idx as usize] = val;
-    }
+/// The fib loop, leaves fib_n(n) in register 7 and at address `result`
+fn fib_source(n: usize) -> String {
+    format!(
+        "
+        start:  li r0 0
+                li r1 1
+                li r2 {}
+        loop:   add r0 r1 r7
+                rw r1 r0
+                rw r7 r1
+                icrr r3
+                ieqe r2 r3 done
+                spc loop
+        done:   wrt r7 result
+                ext
+                .data
+        result: .word 0
+        ",
+        n
+    )
 }
 
-struct Memory<const N: usize> {
-    data: [u64; N], // we assume we have u32 worth of memory
-}
+fn comp_fib(n: usize) -> u64 {
+    let image = asm::assemble_image(&fib_source(n)).expect("fib program should assemble");
 
-impl<const N: usize> Memory<N> {
-    fn new() -> Self {
-        Self { data: [0_u64; N] }
-    }
-}
+    let mut computer = image
+        .load(FlatMemory::new(100))
+        .expect("fib program should fit in memory");
 
-struct CPU<'a, const N: usize> {
-    memory_controller: &'a mut MemoryController<'a, N>,
-    reg_array: [u64; 8],
-    current_instruction: [u8; 8],
-    program_counter: u32,
+    computer.run().expect("fib program should not fault");
+    computer.registers()[7]
 }
 
-impl<'a, const N: usize> CPU<'a, N> {
-    fn print_state(&self) {
-        println!("Registers: {:?}", &self.reg_array);
-        println!("Current I: {:?}", &self.current_instruction);
-        println!("Program C: {}", &self.program_counter);
-        println!("Memory  H: {:?}", &self.mem_header());
-    }
+fn fib_demo() {
+    let n = 75;
 
-    fn mem_header(&self) -> String {
-        format!("{:?}", &self.memory_controller.memory.data[0..30])
-    }
+    let image = asm::assemble_image(&fib_source(n)).expect("fib program should assemble");
 
-    fn new(mc: &'a mut MemoryController<'a, N>) -> Self {
-        Self {
-            memory_controller: mc,
-            reg_array: [0_u64; 8],
-            current_instruction: [0_u8; 8],
-            program_counter: 0_u32,
-        }
-    }
-
-    fn run(&mut self) {
-        let mut last = true;
+    let mut computer = image
+        .load(FlatMemory::new(100))
+        .expect("fib program should fit in memory");
 
-        while last {
-            last = self.cycle();
-        }
+    if let Err(fault) = computer.run_debug() {
+        println!("{}", fault);
     }
+    computer.print_state();
 
-    fn run_debug(&mut self) {
-        let mut last = true;
-
-        while last {
-            last = self.cycle_debug();
-        }
+    for line in disasm::disassemble_memory(computer.memory(), 0..image.data.end() as u32) {
+        println!("{}", line);
     }
 
-    fn cycle(&mut self) -> bool {
-        // load
-        self.load_instruction();
-
-        let tmp_pc = self.program_counter.clone();
-
-        // execute
-        let out = self.execute();
-
-        if tmp_pc == self.program_counter {
-            self.incr();
-        } // else we manipulated the program counter in execute() so we don't want to mess with it here.
+    println!(
+        "comp_fib({}) = {}, fib_n({}) = {}",
+        n,
+        comp_fib(n),
+        n,
+        fib_n(n)
+    );
+}
 
-        out
+/// Sums 1 to n with some busywork in the loop, enough cycles to time
+const BENCH_SOURCE: &str = "
+            li r1 200000
+    loop:   icrr r0
+            add r0 r2 r2
+            xor r2 r0 r3
+            shl r3 r4 r5
+            cmp r0 r1
+            bne loop
+            ext
+";
+
+/// Times `loops` runs of `image` decoding every fetch, then again with the
+/// decoded instruction cache
+fn bench(image: &ProgramImage, loops: u32) -> Result<()> {
+    let mut cycles = 0;
+    let mut times = [0.0; 2];
+    for (time, cached) in times.iter_mut().zip([false, true]) {
+        *time = timeit_loops!(loops, {
+            let mut computer = load(image)?;
+            computer.memory_controller_mut().set_decode_cache(cached);
+            computer.run()?;
+            cycles = computer.cycles();
+        });
     }
 
-    fn cycle_debug(&mut self) -> bool {
-        // load
-        self.load_instruction();
-
-        println!("{:?}", &self.reg_array);
+    println!("{} cycles a run, {} runs each", cycles, loops);
+    for (label, time) in ["decoding every fetch", "decode cache"].iter().zip(times) {
         println!(
-            "PC: {}\nCI: {:?}\n",
-            self.program_counter,
-            deserialize_instruction(self.current_instruction[0])
+            "{:>20}: {:>9.3} ms a run, {:>7.1} M cycles/s",
+            label,
+            time * 1e3,
+            cycles as f64 / time / 1e6
         );
+    }
+    println!("{:>20}: {:.2}x", "speedup", times[0] / times[1]);
+    Ok(())
+}
 
-        let tmp_pc = self.program_counter.clone();
-
-        // execute
-        let out = self.execute();
-
-        if tmp_pc == self.program_counter {
-            self.incr();
-        } // else we manipulated the program counter in execute() so we don't want to mess with it here.
+/// The console devices and a timer on IRQ 0
+fn attach_devices(computer: &mut CPU<PagedMemory>) {
+    device::attach_console(computer.memory_controller_mut());
+    computer
+        .memory_controller_mut()
+        .map_device(device::TIMER, 2, Box::new(Timer::new(0)));
+}
 
-        out
+/// Lists everything the verifier finds wrong with `image`, failing if it found anything
+fn check(image: &ProgramImage) -> Result<()> {
+    let diagnostics = verify::verify(image, PagedMemory::new().words());
+    for diagnostic in &diagnostics {
+        let text = image
+            .code
+            .word(diagnostic.addr)
+            .and_then(disasm::disassemble_word)
+            .unwrap_or_default();
+        eprintln!("{:<30} {}", diagnostic.to_string(), text);
     }
-
-    fn load_instruction(&mut self) {
-        self.current_instruction = self
-            .memory_controller
-            .read(self.program_counter)
-            .to_le_bytes();
+    match diagnostics.len() {
+        0 => Ok(()),
+        n => Err(anyhow!("the verifier found {} problem(s)", n)),
     }
+}
 
-    fn read_from_reg(&self, idx: u8) -> u64 {
-        self.reg_array[idx as usize]
+/// Prints the blocks of `image` with their successors, then its loops and dead code
+fn show_cfg(image: &ProgramImage) {
+    let cfg = Cfg::build(image);
+    let name = |addr: u32| match image.symbols.iter().find(|s| s.addr == addr) {
+        Some(symbol) => format!("{} ({})", addr, symbol.name),
+        None => addr.to_string(),
+    };
+    for block in cfg.blocks() {
+        let successors: Vec<String> = block.edges.iter().map(|edge| name(edge.to)).collect();
+        println!(
+            "block {}..{} -> [{}]",
+            name(block.start),
+            block.end(),
+            successors.join(", ")
+        );
     }
-
-    fn write_to_reg(&mut self, idx: u8, val: u64) {
-        self.reg_array[idx as usize] = val;
+    for l in cfg.loops() {
+        let body: Vec<String> = l.body.iter().map(|&start| name(start)).collect();
+        println!("loop at {}: {}", name(l.header), body.join(", "));
     }
-
-    fn write_to_program_counter(&mut self, val: u32) {
-        self.program_counter = val;
+    for start in cfg.unreachable() {
+        println!("unreachable {}", name(start));
     }
+}
 
-    fn execute(&mut self) -> bool {
-        /// this is gonna be the biggie
+/// Command line machines get the whole 32 bit address space, paged in as
+/// used. Images that don't verify aren't run.
+fn load(image: &ProgramImage) -> Result<CPU<PagedMemory>> {
+    let memory = PagedMemory::new();
+    image.validate(memory.words())?;
+    check(image)?;
+    let mut computer = image.load(memory)?;
+    attach_devices(&mut computer);
+    Ok(computer)
+}
 
-        let out: bool = match deserialize_instruction(self.current_instruction[0]) {
-            Ok(instr) => match instr {
-                /// ext - the computer does nothing, it just dies
-                Instruction::Exit => {
-                    return false;
-                }
-                Instruction::LoadFromMem => {
-                    /// lod <mem_address> <register>
-                    let mem_addr: u32 = deserialize_u32_array(1, &self.current_instruction);
-                    let reg_addr: u8 = self.current_instruction[5];
-                    let val: u64 = self.memory_controller.read(mem_addr);
-                    self.write_to_reg(reg_addr, val);
-                    return true;
-                }
-                /// wrt <register> <mem_address>
-                Instruction::WriteToMem => {
-                    let reg_addr: u8 = self.current_instruction[1];
-                    let mem_addr: u32 = deserialize_u32_array(2, &self.current_instruction);
-                    let out = self.read_from_reg(reg_addr);
-                    self.memory_controller.write(mem_addr, out);
-                    return true;
-                }
-                /// add <reg1> <reg2> <reg3> - adds reg1 to reg2 and writes to reg3
-                Instruction::Add => {
-                    let read_1_addr: u8 = self.current_instruction[1];
-                    let read_2_addr: u8 = self.current_instruction[2];
-                    let write_addr: u8 = self.current_instruction[3];
+/// Leaves the process with the program's exit code if it set one
+fn finish(computer: &CPU<PagedMemory>) {
+    computer.print_state();
+    if let Some(code) = computer.exit_code() {
+        std::process::exit(code as i32);
+    }
+}
 
-                    let out = self.read_from_reg(read_1_addr) + self.read_from_reg(read_2_addr);
-                    self.write_to_reg(write_addr, out);
+/// Runs an image with every instruction traced to `out`, `-` meaning stdout
+fn run_traced(path: &str, out: &str, format: TraceFormat) -> Result<()> {
+    let mut computer = load(&ProgramImage::read_from(path)?)?;
+    let out: Box<dyn std::io::Write> = match out {
+        "-" => Box::new(std::io::stdout()),
+        _ => Box::new(std::io::BufWriter::new(
+            std::fs::File::create(out).map_err(|e| anyhow!("{}: {}", out, e))?,
+        )),
+    };
+    let mut tracer = Tracer::new(out, format)?;
+    let run = computer.run_traced(&mut tracer);
+    tracer.into_inner()?;
+    run
+}
 
-                    return true;
-                }
-                /// sub <reg1> <reg2> <reg3> - subs reg1 to reg2 and writes to reg3
-                Instruction::Sub => {
-                    let read_1_addr: u8 = self.current_instruction[1];
-                    let read_2_addr: u8 = self.current_instruction[2];
-                    let write_addr: u8 = self.current_instruction[3];
+/// `--pc <lo>..<hi>`, `--reg <rN>` and `--addr <addr>`, in any order
+fn parse_filter(args: &[&str]) -> Result<TraceFilter> {
+    let number = |arg: &str| {
+        arg.parse::<u32>()
+            .map_err(|_| anyhow!("bad number `{}`", arg))
+    };
+    let mut filter = TraceFilter::default();
+    for pair in args.chunks(2) {
+        match pair {
+            ["--pc", range] => {
+                let (lo, hi) = range
+                    .split_once("..")
+                    .ok_or_else(|| anyhow!("expected <lo>..<hi>, found `{}`", range))?;
+                filter.pcs = Some(number(lo)?..number(hi)?);
+            }
+            ["--reg", reg] => {
+                let idx = reg
+                    .strip_prefix('r')
+                    .and_then(|idx| idx.parse::<u8>().ok())
+                    .filter(|idx| *idx < 8)
+                    .ok_or_else(|| anyhow!("bad register `{}`", reg))?;
+                filter.register = Some(idx);
+            }
+            ["--addr", addr] => filter.addr = Some(number(addr)?),
+            _ => return Err(anyhow!("{}", USAGE)),
+        }
+    }
+    Ok(filter)
+}
 
-                    let out = self.read_from_reg(read_1_addr) + self.read_from_reg(read_2_addr);
-                    self.write_to_reg(write_addr, out);
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
 
-                    return true;
-                }
-                /// spc <u32_value> - sets the program counter to the u32 in the instruction
-                Instruction::SetProgramCounter => {
-                    self.write_to_program_counter(deserialize_u32_array(
-                        1,
-                        &self.current_instruction,
-                    ));
-                    return true;
-                }
-                /// clra - sets every register to zero
-                Instruction::ClearAllRegisters => {
-                    for idx in 0..8 {
-                        self.write_to_reg(idx, 0);
-                    }
-                    return true;
-                }
-                /// clr <reg_addr> - sets this register to zero
-                Instruction::ClearRegister => {
-                    self.write_to_reg(self.current_instruction[1], 0);
-                    return true;
+    match args.as_slice() {
+        [] => fib_demo(),
+        ["asm", source, out] => {
+            let src = std::fs::read_to_string(source).map_err(|e| anyhow!("{}: {}", source, e))?;
+            let image = asm::assemble_image(&src).map_err(|e| anyhow!("{}: {}", source, e))?;
+            image.write_to(out)?;
+        }
+        ["disasm", path] => {
+            let image = ProgramImage::read_from(path)?;
+            println!("entry {}", image.entry);
+            let code = disasm::disassemble(&image.code.words, image.code.base);
+            let data = disasm::data_listing(&image.data.words, image.data.base);
+            for line in code.into_iter().chain(data) {
+                match image.symbols.iter().find(|s| s.addr == line.addr) {
+                    Some(symbol) => println!("{}:\n{}", symbol.name, line),
+                    None => println!("{}", line),
                 }
-                /// rw <reg1> <reg2> - writes the value of register 1 to register 2
-                Instruction::RegisterWrite => {
-                    let idxleft = self.current_instruction[1];
-                    let idxright = self.current_instruction[2];
-                    self.write_to_reg(idxright, self.read_from_reg(idxleft));
-                    return true;
+            }
+        }
+        ["verify", path] => {
+            check(&ProgramImage::read_from(path)?)?;
+            println!("{}: ok", path);
+        }
+        ["cfg", path] => show_cfg(&ProgramImage::read_from(path)?),
+        ["cfg", path, dot] => {
+            let image = ProgramImage::read_from(path)?;
+            show_cfg(&image);
+            std::fs::write(dot, Cfg::build(&image).to_dot(&image.symbols))
+                .map_err(|e| anyhow!("{}: {}", dot, e))?;
+        }
+        ["run", path] => {
+            let mut computer = load(&ProgramImage::read_from(path)?)?;
+            computer.run()?;
+            finish(&computer);
+        }
+        ["run", "--budget", budget, path] => {
+            let budget: u64 = budget
+                .parse()
+                .map_err(|_| anyhow!("bad budget `{}`", budget))?;
+            let mut computer = load(&ProgramImage::read_from(path)?)?;
+            if let RunOutcome::OutOfFuel { cycles } = computer.run_with_budget(budget)? {
+                computer.print_state();
+                return Err(anyhow!("out of fuel after {} cycles", cycles));
+            }
+            finish(&computer);
+        }
+        ["run", "--profile", path] => {
+            let image = ProgramImage::read_from(path)?;
+            let mut computer = load(&image)?;
+            let mut profile = Profile::new();
+            let run = computer.run_profiled(&mut profile);
+            print!("{}", profile.report(&image.symbols, 20));
+            run?;
+            finish(&computer);
+        }
+        ["run", "--folded", out, path] => {
+            let image = ProgramImage::read_from(path)?;
+            let mut computer = load(&image)?;
+            let mut profile = Profile::new();
+            let run = computer.run_profiled(&mut profile);
+            std::fs::write(out, profile.folded(&image.symbols))
+                .map_err(|e| anyhow!("{}: {}", out, e))?;
+            run?;
+            finish(&computer);
+        }
+        ["run", "--debug", path] => {
+            let mut computer = load(&ProgramImage::read_from(path)?)?;
+            computer.run_debug()?;
+            finish(&computer);
+        }
+        ["debug", path] => {
+            let image = ProgramImage::read_from(path)?;
+            let mut computer = load(&image)?;
+            // stdin belongs to the debugger, the program sees no input
+            computer.memory_controller_mut().map_device(
+                device::CONSOLE_IN,
+                1,
+                Box::new(ConsoleIn::from_buffer(Vec::new())),
+            );
+            let mut debugger = Debugger::new(computer).with_symbols(image.symbols);
+            debugger.repl(std::io::stdin().lock(), &mut std::io::stdout(), true)?;
+        }
+        ["debug", path, script] => {
+            let image = ProgramImage::read_from(path)?;
+            let mut debugger = Debugger::new(load(&image)?).with_symbols(image.symbols);
+            let script = std::fs::File::open(script).map_err(|e| anyhow!("{}: {}", script, e))?;
+            debugger.repl(
+                std::io::BufReader::new(script),
+                &mut std::io::stdout(),
+                false,
+            )?;
+        }
+        ["resume", image, snapshot] => {
+            // snapshots don't hold configuration, the memory permissions come from the image
+            let mut computer = load(&ProgramImage::read_from(image)?)?;
+            computer.restore_snapshot(snapshot)?;
+            computer.run()?;
+            finish(&computer);
+        }
+        ["bench"] => bench(&asm::assemble_image(BENCH_SOURCE)?, 5)?,
+        ["bench", path] => bench(&ProgramImage::read_from(path)?, 5)?,
+        ["trace", path, out] => run_traced(path, out, TraceFormat::Json)?,
+        ["trace", "--binary", path, out] => run_traced(path, out, TraceFormat::Binary)?,
+        ["trace-filter", path, filter @ ..] => {
+            let filter = parse_filter(filter)?;
+            for record in trace::read_trace_file(path)? {
+                if filter.matches(&record) {
+                    println!("{}", record.to_json());
                 }
-                /// ieqe <reg1> <reg2> <u32_program_counter>
-                Instruction::IfEqSPCElsePass => {
-                    let reg1 = self.current_instruction[1];
-                    let reg2 = self.current_instruction[2];
-                    if self.read_from_reg(reg1) == self.read_from_reg(reg2) {
-                        let pcu32 = deserialize_u32_array(3, &self.current_instruction);
-                        self.program_counter = pcu32;
+            }
+        }
+        ["trace-diff", left, right] => {
+            let left_records = trace::read_trace_file(left)?;
+            let right_records = trace::read_trace_file(right)?;
+            match trace::diverge(&left_records, &right_records) {
+                None => println!("traces agree for all {} records", left_records.len()),
+                Some(divergence) => {
+                    println!("traces diverge at record {}", divergence.index);
+                    for (name, record) in [(left, divergence.left), (right, divergence.right)] {
+                        match record {
+                            Some(record) => println!("{}: {}", name, record.to_json()),
+                            None => println!("{}: <end of trace>", name),
+                        }
                     }
-                    return true;
-                }
-                /// icrr <reg> - adds one to the register
-                Instruction::IncrementReg => {
-                    let reg = self.current_instruction[1];
-                    let out = self.read_from_reg(reg) + 1;
-                    self.write_to_reg(reg, out);
-                    return true;
+                    std::process::exit(1);
                 }
-            },
-            Err(x) => {
-                panic!("{}", x);
             }
-        };
-        out
-    }
-
-    fn incr(&mut self) {
-        self.program_counter += 1;
-    }
-}
-
-fn fib_n(n: usize) -> u64 {
-    let mut a = 0_u64;
-    let mut b = 1_u64;
-
-    for _ in 0..n {
-        let tmp = b + a;
-        a = b;
-        b = tmp;
+        }
+        _ => return Err(anyhow!("{}", USAGE)),
     }
 
-    return b;
-}
-
-// we want everything to be little endian
-
-fn comp_fib(n: usize) {
-    let mut memory = Memory::<100>::new();
-
-    /*
-    [
-        0, // val
-        1, // val
-        100, // val
-        [1, 0,0,0,0, 0, 0,0], // load 1
-        [1, 1,0,0,0, 1, 0,0], // load 2
-        [1, 2,0,0,0, 3, 0,0], // load 3
-        [9, 3, 4, 0,0,0,0, 0] // if reg4 == reg 3, set to mem0
-        [3, 0, 1, 7, 0,0,0],  // add
-        [8, 1, 0, 0,0,0,0,0] // reg write
-        [8, 7, 1, 0,0,0,0,0] // reg write
-        [10, 4, 0,0,0,0,0,0] // increment register 4
-        [5, 6,0,0,0, 0,0,0] // spc 6
-
-    ]
-    */
-
-    let program: Vec<u64> = vec![
-        0,                                       // val
-        1,                                       // val
-        n as u64,                                      // val
-        incode_instr([1, 0, 0, 0, 0, 0, 0, 0]),  // load 1
-        incode_instr([1, 1, 0, 0, 0, 1, 0, 0]),  // load 2
-        incode_instr([1, 2, 0, 0, 0, 2, 0, 0]),  // load 3
-        incode_instr([9, 2, 3, 0, 0, 0, 0, 0]),  // ifelsepass
-        incode_instr([3, 0, 1, 7, 0, 0, 0, 0]),  // add
-        incode_instr([8, 1, 0, 0, 0, 0, 0, 0]),  // reg write
-        incode_instr([8, 7, 1, 0, 0, 0, 0, 0]),  // reg write
-        incode_instr([10, 3, 0, 0, 0, 0, 0, 0]), // increment register 4
-        incode_instr([5, 6, 0, 0, 0, 0, 0, 0]),  // spc 6
-    ];
-
-    let mut memory_controller = MemoryController::new_from(&mut memory);
-
-    memory_controller.load_program_external(&program, 0);
-
-    let mut computer = CPU::new(&mut memory_controller);
-    computer.program_counter = 3;
-    computer.run();
-}
-
-fn main() {
-    let mut memory = Memory::<100>::new();
-    let n = 75;
-    /*
-    [
-        0, // val
-        1, // val
-        100, // val
-        [1, 0,0,0,0, 0, 0,0], // load 1
-        [1, 1,0,0,0, 1, 0,0], // load 2
-        [1, 2,0,0,0, 3, 0,0], // load 3
-        [9, 3, 4, 0,0,0,0, 0] // if reg4 == reg 3, set to mem0
-        [3, 0, 1, 7, 0,0,0],  // add
-        [8, 1, 0, 0,0,0,0,0] // reg write
-        [8, 7, 1, 0,0,0,0,0] // reg write
-        [10, 4, 0,0,0,0,0,0] // increment register 4
-        [5, 6,0,0,0, 0,0,0] // spc 6
-
-    ]
-    */
-
-    let program: Vec<u64> = vec![
-        0,                                       // val
-        1,                                       // val
-        n as u64,                                      // val
-        incode_instr([1, 0, 0, 0, 0, 0, 0, 0]),  // load 1
-        incode_instr([1, 1, 0, 0, 0, 1, 0, 0]),  // load 2
-        incode_instr([1, 2, 0, 0, 0, 2, 0, 0]),  // load 3
-        incode_instr([3, 0, 1, 7, 0, 0, 0, 0]),  // add
-        incode_instr([8, 1, 0, 0, 0, 0, 0, 0]),  // reg write
-        incode_instr([8, 7, 1, 0, 0, 0, 0, 0]),  // reg write
-        incode_instr([10, 3, 0, 0, 0, 0, 0, 0]), // increment register 4
-        incode_instr([9, 2, 3, 12, 0, 0, 0, 0]),  // ifelsepass
-        incode_instr([5, 6, 0, 0, 0, 0, 0, 0]),  // spc 6
-        incode_instr([2,7,20,0,0,0,0,0])         // write from reg to mem
-    ];
-
-    let mut memory_controller = MemoryController::new_from(&mut memory);
-
-    memory_controller.load_program_external(&program, 0);
-
-    let mut computer = CPU::new(&mut memory_controller);
-    computer.program_counter = 3;
-    computer.run_debug();
-    computer.print_state();
+    Ok(())
 }
@@ -0,0 +1,502 @@
+use crate::decode::{DecodeCache, Op};
+use crate::device::Device;
+use crate::fault::{Access, VmFault};
+use std::collections::HashMap;
+use std::fmt;
+
+/**
+ * Routes addresses to a mapped `Device` if there is one, RAM otherwise.
+ * Every `read`, `write` and `fetch` is checked against the permissions of
+ * the region the address falls in first.
+ *
+ * Instructions fetched from RAM are decoded once and cached until their
+ * word is written, through `write` or by anything holding `memory_mut`.
+ */
+pub struct MemoryController<M: MemoryBackend> {
+    memory: M,
+    devices: Vec<Mapping>,
+    regions: Vec<Region>,             // newest first
+    default_permissions: Permissions, // for addresses outside every region
+    decoded: Option<DecodeCache>,     // None with the cache turned off
+    write_log: Option<Vec<MemoryWrite>>,
+}
+
+/// What a region of memory may be used for
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Permissions {
+    pub const NONE: Self = Self::new(false, false, false);
+    pub const R: Self = Self::new(true, false, false);
+    pub const RW: Self = Self::new(true, true, false);
+    pub const RX: Self = Self::new(true, false, true);
+    pub const RWX: Self = Self::new(true, true, true);
+
+    pub const fn new(read: bool, write: bool, execute: bool) -> Self {
+        Self {
+            read,
+            write,
+            execute,
+        }
+    }
+
+    pub fn allows(&self, access: Access) -> bool {
+        match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Execute => self.execute,
+        }
+    }
+}
+
+impl fmt::Display for Permissions {
+    /// `rwx` style, a dash for each missing permission
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bit = |set: bool, c: char| if set { c } else { '-' };
+        write!(
+            f,
+            "{}{}{}",
+            bit(self.read, 'r'),
+            bit(self.write, 'w'),
+            bit(self.execute, 'x')
+        )
+    }
+}
+
+struct Region {
+    base: u32,
+    len: u32,
+    permissions: Permissions,
+}
+
+/// One successful `MemoryController::write`, as recorded by the write log
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryWrite {
+    pub addr: u32,
+    pub old: u64, // what `peek` saw there before
+    pub new: u64,
+}
+
+struct Mapping {
+    base: u32,
+    len: u32,
+    device: Box<dyn Device>,
+}
+
+impl Mapping {
+    fn offset(&self, idx: u32) -> Option<u32> {
+        idx.checked_sub(self.base)
+            .filter(|offset| *offset < self.len)
+    }
+}
+
+impl<M: MemoryBackend> MemoryController<M> {
+    pub fn new_from(input: M) -> Self {
+        Self {
+            memory: input,
+            devices: Vec::new(),
+            regions: Vec::new(),
+            default_permissions: Permissions::RWX,
+            decoded: Some(DecodeCache::default()),
+            write_log: None,
+        }
+    }
+
+    /// Starts recording every write, dropping anything recorded so far
+    pub fn start_write_log(&mut self) {
+        self.write_log = Some(Vec::new());
+    }
+
+    /// Stops recording and hands back the writes since `start_write_log`
+    pub fn take_write_log(&mut self) -> Vec<MemoryWrite> {
+        self.write_log.take().unwrap_or_default()
+    }
+
+    /// Puts `device` on `base..base + len`, shadowing RAM and any device
+    /// mapped there before it
+    pub fn map_device(&mut self, base: u32, len: u32, device: Box<dyn Device>) {
+        if let Some(cache) = &mut self.decoded {
+            cache.invalidate_range(base as u64, base as u64 + len as u64);
+        }
+        self.devices.insert(0, Mapping { base, len, device });
+    }
+
+    /// Gives `base..base + len` its own permissions, shadowing any region
+    /// set before it
+    pub fn protect(&mut self, base: u32, len: u32, permissions: Permissions) {
+        self.regions.insert(
+            0,
+            Region {
+                base,
+                len,
+                permissions,
+            },
+        );
+    }
+
+    /// What addresses outside every region allow, everything by default
+    pub fn set_default_permissions(&mut self, permissions: Permissions) {
+        self.default_permissions = permissions;
+    }
+
+    pub fn default_permissions(&self) -> Permissions {
+        self.default_permissions
+    }
+
+    /// The permissions in force at `idx`
+    pub fn permissions(&self, idx: u32) -> Permissions {
+        self.regions
+            .iter()
+            .find(|region| {
+                idx.checked_sub(region.base)
+                    .is_some_and(|offset| offset < region.len)
+            })
+            .map_or(self.default_permissions, |region| region.permissions)
+    }
+
+    fn check(&self, idx: u32, access: Access) -> Result<(), VmFault> {
+        if self.permissions(idx).allows(access) {
+            Ok(())
+        } else {
+            Err(VmFault::Protection(idx, access))
+        }
+    }
+
+    /// Turns the decoded instruction cache on or off, it starts on. Off,
+    /// every fetch decodes its word again.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decoded = enabled.then(DecodeCache::default);
+    }
+
+    pub fn decode_cache(&self) -> bool {
+        self.decoded.is_some()
+    }
+
+    fn device_at(&mut self, idx: u32) -> Option<(&mut Box<dyn Device>, u32)> {
+        self.devices.iter_mut().find_map(|mapping| {
+            let offset = mapping.offset(idx)?;
+            Some((&mut mapping.device, offset))
+        })
+    }
+
+    /// True if `idx` goes to a device rather than RAM
+    pub fn is_device(&self, idx: u32) -> bool {
+        self.devices
+            .iter()
+            .any(|mapping| mapping.offset(idx).is_some())
+    }
+
+    /// Ticks every device, returns the IRQ lines they raised as a bit mask
+    pub fn tick(&mut self) -> u16 {
+        let mut raised = 0;
+        for mapping in &mut self.devices {
+            if let Some(line) = mapping.device.tick() {
+                raised |= 1_u16.checked_shl(line as u32).unwrap_or(0);
+            }
+        }
+        raised
+    }
+
+    /// Base, length and saved state of every mapped device, newest first
+    pub fn save_devices(&self) -> Vec<(u32, u32, Vec<u8>)> {
+        self.devices
+            .iter()
+            .map(|mapping| (mapping.base, mapping.len, mapping.device.save()))
+            .collect()
+    }
+
+    /// Fails unless every state in `save_devices` output has a device
+    /// mapped at its range that would take it, changing nothing
+    pub fn check_devices(&self, states: &[(u32, u32, Vec<u8>)]) -> anyhow::Result<()> {
+        for (base, len, state) in states {
+            let mapping = self
+                .devices
+                .iter()
+                .find(|mapping| (mapping.base, mapping.len) == (*base, *len))
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "no device mapped at {}..{}",
+                        base,
+                        *base as u64 + *len as u64
+                    )
+                })?;
+            mapping.device.check_state(state)?;
+        }
+        Ok(())
+    }
+
+    /// Hands `save_devices` output back to the newest device mapped at the
+    /// same range. Every state is checked first, a bad one leaves all the
+    /// devices as they were.
+    pub fn restore_devices(&mut self, states: &[(u32, u32, Vec<u8>)]) -> anyhow::Result<()> {
+        self.check_devices(states)?;
+        for (base, len, state) in states {
+            let mapping = self
+                .devices
+                .iter_mut()
+                .find(|mapping| (mapping.base, mapping.len) == (*base, *len))
+                .unwrap();
+            mapping.device.restore(state)?;
+        }
+        Ok(())
+    }
+
+    /// The first exit code a device asked for, if any
+    pub fn exit_code(&self) -> Option<u64> {
+        self.devices
+            .iter()
+            .find_map(|mapping| mapping.device.exit_code())
+    }
+
+    /// Copies a program into memory, read only regions included
+    pub fn load_program_external(&mut self, ext_prg: &[u64], idx: usize) -> Result<(), VmFault> {
+        for (i, word) in ext_prg.iter().enumerate() {
+            self.store((i + idx) as u32, *word)?;
+        }
+        Ok(())
+    }
+
+    pub fn read(&mut self, idx: u32) -> Result<u64, VmFault> {
+        self.check(idx, Access::Read)?;
+        self.load(idx)
+    }
+
+    /// `read` for instruction fetches, needs execute permission instead
+    pub fn fetch(&mut self, idx: u32) -> Result<u64, VmFault> {
+        self.check(idx, Access::Execute)?;
+        self.load(idx)
+    }
+
+    /// `fetch` plus the decoded instruction, from the cache when it can be
+    pub(crate) fn fetch_decoded(&mut self, idx: u32) -> Result<(u64, Op), VmFault> {
+        if let Some(hit) = self.decoded.as_ref().and_then(|cache| cache.get(idx)) {
+            // permissions can change after an instruction is cached
+            self.check(idx, Access::Execute)?;
+            return Ok(hit);
+        }
+        let raw = self.fetch(idx)?;
+        let op = Op::decode(raw);
+        // device words can change without being written, never cache them
+        if !self.is_device(idx) {
+            if let Some(cache) = &mut self.decoded {
+                cache.insert(idx, raw, op);
+            }
+        }
+        Ok((raw, op))
+    }
+
+    fn load(&mut self, idx: u32) -> Result<u64, VmFault> {
+        if let Some((device, offset)) = self.device_at(idx) {
+            return device.read(offset);
+        }
+        self.memory.read(idx)
+    }
+
+    pub fn write(&mut self, idx: u32, val: u64) -> Result<(), VmFault> {
+        self.check(idx, Access::Write)?;
+        self.store(idx, val)
+    }
+
+    fn store(&mut self, idx: u32, val: u64) -> Result<(), VmFault> {
+        let old = match self.write_log {
+            Some(_) => self.peek(idx).unwrap_or(0),
+            None => 0,
+        };
+        if let Some((device, offset)) = self.device_at(idx) {
+            device.write(offset, val)?;
+        } else {
+            self.memory.write(idx, val)?;
+            if let Some(cache) = &mut self.decoded {
+                cache.invalidate(idx);
+            }
+        }
+        if let Some(log) = &mut self.write_log {
+            log.push(MemoryWrite {
+                addr: idx,
+                old,
+                new: val,
+            });
+        }
+        Ok(())
+    }
+
+    /// Like `write` but ignoring permissions, for debuggers patching code
+    pub fn poke(&mut self, idx: u32, val: u64) -> Result<(), VmFault> {
+        self.store(idx, val)
+    }
+
+    /// Like `read` but without side effects, for debuggers and dumps
+    pub fn peek(&self, idx: u32) -> Result<u64, VmFault> {
+        for mapping in &self.devices {
+            if let Some(offset) = mapping.offset(idx) {
+                return Ok(mapping.device.peek(offset));
+            }
+        }
+        self.memory.read(idx)
+    }
+
+    pub fn memory(&self) -> &M {
+        &self.memory
+    }
+
+    /// Drops every cached instruction, since nothing written through this
+    /// goes past the controller
+    pub fn memory_mut(&mut self) -> &mut M {
+        if let Some(cache) = &mut self.decoded {
+            cache.clear();
+        }
+        &mut self.memory
+    }
+
+    pub fn into_memory(self) -> M {
+        self.memory
+    }
+}
+
+/**
+ * Where RAM words actually live. Addresses are u32s, a backend holds the
+ * words from 0 up to `words() - 1` and reports anything past that as
+ * `MemoryOutOfRange`.
+ */
+pub trait MemoryBackend {
+    /// How many words are addressable, at most 2^32
+    fn words(&self) -> u64;
+
+    fn read(&self, idx: u32) -> Result<u64, VmFault>;
+
+    fn write(&mut self, idx: u32, val: u64) -> Result<(), VmFault>;
+
+    /// The stretches of memory that have been allocated, in address order,
+    /// every word outside them reads as zero
+    fn chunks(&self) -> Vec<(u32, &[u64])>;
+
+    /// Sets every word back to zero
+    fn clear(&mut self);
+}
+
+/// A fixed number of words in one heap allocation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlatMemory {
+    data: Vec<u64>,
+}
+
+impl FlatMemory {
+    /// `words` zeroed words, at most 2^32
+    pub fn new(words: usize) -> Self {
+        assert!(
+            words as u64 <= 1 << 32,
+            "a u32 can't address {} words",
+            words
+        );
+        Self {
+            data: vec![0_u64; words],
+        }
+    }
+
+    pub fn data(&self) -> &[u64] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u64] {
+        &mut self.data
+    }
+}
+
+impl MemoryBackend for FlatMemory {
+    fn words(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    fn read(&self, idx: u32) -> Result<u64, VmFault> {
+        self.data
+            .get(idx as usize)
+            .copied()
+            .ok_or(VmFault::MemoryOutOfRange(idx))
+    }
+
+    fn write(&mut self, idx: u32, val: u64) -> Result<(), VmFault> {
+        let slot = self
+            .data
+            .get_mut(idx as usize)
+            .ok_or(VmFault::MemoryOutOfRange(idx))?;
+        *slot = val;
+        Ok(())
+    }
+
+    fn chunks(&self) -> Vec<(u32, &[u64])> {
+        vec![(0, &self.data[..])]
+    }
+
+    fn clear(&mut self) {
+        self.data.fill(0);
+    }
+}
+
+/// Words per page of `PagedMemory`
+pub const PAGE_WORDS: usize = 1 << 10;
+
+/**
+ * The whole u32 address space, allocated a page at a time on the first
+ * nonzero write. Reads of untouched pages see zero without allocating.
+ */
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PagedMemory {
+    pages: HashMap<u32, Box<[u64]>>, // keyed by address / PAGE_WORDS
+}
+
+impl PagedMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pages allocated so far
+    pub fn pages(&self) -> usize {
+        self.pages.len()
+    }
+}
+
+impl MemoryBackend for PagedMemory {
+    fn words(&self) -> u64 {
+        1 << 32
+    }
+
+    fn read(&self, idx: u32) -> Result<u64, VmFault> {
+        let page = idx / PAGE_WORDS as u32;
+        let offset = idx as usize % PAGE_WORDS;
+        Ok(self.pages.get(&page).map_or(0, |words| words[offset]))
+    }
+
+    fn write(&mut self, idx: u32, val: u64) -> Result<(), VmFault> {
+        let page = idx / PAGE_WORDS as u32;
+        let offset = idx as usize % PAGE_WORDS;
+        match self.pages.get_mut(&page) {
+            Some(words) => words[offset] = val,
+            // zero is what an untouched page reads as anyway
+            None if val == 0 => {}
+            None => {
+                let mut words = vec![0_u64; PAGE_WORDS].into_boxed_slice();
+                words[offset] = val;
+                self.pages.insert(page, words);
+            }
+        }
+        Ok(())
+    }
+
+    fn chunks(&self) -> Vec<(u32, &[u64])> {
+        let mut chunks: Vec<_> = self
+            .pages
+            .iter()
+            .map(|(page, words)| (page * PAGE_WORDS as u32, &words[..]))
+            .collect();
+        chunks.sort_by_key(|(base, _)| *base);
+        chunks
+    }
+
+    fn clear(&mut self) {
+        self.pages.clear();
+    }
+}
@@ -0,0 +1,213 @@
+use crate::disasm;
+use crate::fault::Fault;
+use crate::image::Symbol;
+use crate::{Instruction, MemoryBackend, CPU};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Counts for one program counter value
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct PcStats {
+    pub count: u64,
+    pub raw: u64,       // the word last executed there
+    pub taken: u64,     // conditional branches only
+    pub not_taken: u64, // conditional branches only
+}
+
+/**
+ * Execution counts gathered by `CPU::run_profiled`: per program counter, per
+ * opcode, taken and not taken for conditional branches, and folded call
+ * stacks built by following `call`/`ret` and interrupt entry/`iret`.
+ */
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pcs: BTreeMap<u32, PcStats>,
+    opcodes: [u64; 256],
+    stack: Vec<u32>, // entry addresses of the functions currently running, outermost first
+    folded: BTreeMap<Vec<u32>, u64>,
+    total: u64,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self {
+            pcs: BTreeMap::new(),
+            opcodes: [0; 256],
+            stack: Vec::new(),
+            folded: BTreeMap::new(),
+            total: 0,
+        }
+    }
+
+    /// Instructions executed while profiling
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn pc(&self, pc: u32) -> Option<&PcStats> {
+        self.pcs.get(&pc)
+    }
+
+    pub fn opcode_count(&self, instruction: Instruction) -> u64 {
+        self.opcodes[instruction as usize]
+    }
+
+    /// Every executed address, most executed first
+    pub fn hot_spots(&self) -> Vec<(u32, PcStats)> {
+        let mut spots: Vec<_> = self.pcs.iter().map(|(pc, stats)| (*pc, *stats)).collect();
+        spots.sort_by(|a, b| b.1.count.cmp(&a.1.count).then(a.0.cmp(&b.0)));
+        spots
+    }
+
+    fn record(&mut self, pc: u32, raw: u64, next_pc: u32) {
+        self.total += 1;
+        let stats = self.pcs.entry(pc).or_default();
+        stats.count += 1;
+        stats.raw = raw;
+
+        let instruction: Option<Instruction> = num::FromPrimitive::from_u8(raw as u8);
+        self.opcodes[raw as u8 as usize] += 1;
+        if self.stack.is_empty() {
+            self.stack.push(pc);
+        }
+        *self.folded.entry(self.stack.clone()).or_default() += 1;
+
+        match instruction {
+            Some(
+                Instruction::IfEqSPCElsePass
+                | Instruction::BranchEq
+                | Instruction::BranchNe
+                | Instruction::BranchLt
+                | Instruction::BranchLe
+                | Instruction::BranchGt
+                | Instruction::BranchGe
+                | Instruction::BranchLtU
+                | Instruction::BranchLeU
+                | Instruction::BranchGtU
+                | Instruction::BranchGeU,
+            ) => {
+                if next_pc == pc.wrapping_add(1) {
+                    stats.not_taken += 1;
+                } else {
+                    stats.taken += 1;
+                }
+            }
+            Some(Instruction::Call) => self.stack.push(next_pc),
+            // never pop the outermost frame, a stray ret just stays in it
+            Some(Instruction::Return | Instruction::InterruptReturn) if self.stack.len() > 1 => {
+                self.stack.pop();
+            }
+            Some(Instruction::Return | Instruction::InterruptReturn) => {}
+            Some(Instruction::SetProgramCounter) => {}
+            // anything else landing somewhere odd faulted into a handler
+            _ if next_pc != pc.wrapping_add(1) => self.stack.push(next_pc),
+            _ => {}
+        }
+    }
+
+    /**
+     * The hot spots as a table, at most `limit` rows, each with its share of
+     * the run and its disassembly. Addresses with a symbol get its name.
+     */
+    pub fn report(&self, symbols: &[Symbol], limit: usize) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{} instructions executed", self.total);
+        let _ = writeln!(out, "{:>10} {:>6} {:>6}  instruction", "count", "%", "pc");
+        for (pc, stats) in self.hot_spots().into_iter().take(limit) {
+            if let Some(symbol) = symbols.iter().find(|s| s.addr == pc) {
+                let _ = writeln!(out, "{}:", symbol.name);
+            }
+            let _ = write!(
+                out,
+                "{:>10} {:>6.2} {:>6}  {}",
+                stats.count,
+                percent(stats.count, self.total),
+                pc,
+                disasm::disassemble_word(stats.raw)
+                    .unwrap_or_else(|| format!(".word {}", stats.raw))
+            );
+            if stats.taken + stats.not_taken > 0 {
+                let _ = write!(out, "  taken {} not {}", stats.taken, stats.not_taken);
+            }
+            out.push('\n');
+        }
+
+        let mut opcodes: Vec<(Instruction, u64)> = (0..=u8::MAX)
+            .filter_map(num::FromPrimitive::from_u8)
+            .map(|instruction: Instruction| (instruction, self.opcode_count(instruction)))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        opcodes.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        let _ = writeln!(out, "{:>10} {:>6}  opcode", "count", "%");
+        for (instruction, count) in opcodes {
+            let _ = writeln!(
+                out,
+                "{:>10} {:>6.2}  {}",
+                count,
+                percent(count, self.total),
+                instruction.mnemonic()
+            );
+        }
+        out
+    }
+
+    /**
+     * Folded stacks, one `outer;inner;innermost count` line per distinct
+     * call stack, the format flamegraph tools take. Frames are named after
+     * the symbol at the function's entry, or its address in hex.
+     */
+    pub fn folded(&self, symbols: &[Symbol]) -> String {
+        let name = |addr: u32| match symbols.iter().find(|s| s.addr == addr) {
+            Some(symbol) => symbol.name.clone(),
+            None => format!("{:#x}", addr),
+        };
+        let mut out = String::new();
+        for (stack, count) in &self.folded {
+            let frames: Vec<String> = stack.iter().map(|addr| name(*addr)).collect();
+            let _ = writeln!(out, "{} {}", frames.join(";"), count);
+        }
+        out
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn percent(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+impl<M: MemoryBackend> CPU<M> {
+    /// `step`, counting the instruction in `profile`. Faulting instructions
+    /// that reach the host aren't counted.
+    pub fn step_profiled(&mut self, profile: &mut Profile) -> Result<bool, Fault> {
+        let before = self.program_counter();
+        self.poll_interrupts();
+        let pc = self.program_counter();
+        if pc != before {
+            // an interrupt handler runs as if it had been called
+            if profile.stack.is_empty() {
+                profile.stack.push(before);
+            }
+            profile.stack.push(pc);
+        }
+
+        let out = self.fetch_execute()?;
+        let raw = u64::from_le_bytes(self.current_instruction());
+        profile.record(pc, raw, self.program_counter());
+        Ok(out)
+    }
+
+    /// `run` with every instruction counted in `profile`
+    pub fn run_profiled(&mut self, profile: &mut Profile) -> Result<(), Fault> {
+        while self.step_profiled(profile)? {}
+        Ok(())
+    }
+}
@@ -0,0 +1,207 @@
+use crate::codec::{put_u16, put_u32, put_u64, put_u8, Reader};
+use crate::{Flags, MemoryBackend, CPU};
+use anyhow::{anyhow, Result};
+use std::path::Path;
+
+/// Start of every snapshot
+pub const MAGIC: [u8; 4] = *b"RVMX";
+/// Bumped whenever the layout below changes, older snapshots still restore
+pub const SNAPSHOT_VERSION: u16 = 2;
+
+impl<M: MemoryBackend> CPU<M> {
+    /**
+     * Snapshot layout, all little endian:
+     *
+     * ```text
+     * magic "RVMX", version u16, reserved u16, memory words u64 (u32 in version 1)
+     * registers 8 x u64, pc u32, current instruction 8 bytes
+     * flags u64 (`Flags::bits`), sp u32, stack base u32, stack top u32
+     * pending irqs u16, interrupts enabled u8, vector table u8 + u32, cycles u64
+     * memory runs u32, then per run: address u32, count u32, count x u64
+     * devices u32, then per device: base u32, len u32, state len u32, state
+     * ```
+     *
+     * Memory is stored as runs of nonzero words so mostly empty machines stay
+     * small, whichever backend they use. Configuration isn't state: the cost
+     * table, memory permissions and the devices themselves come from whoever
+     * builds the machine being restored into.
+     */
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        put_u16(&mut out, SNAPSHOT_VERSION);
+        put_u16(&mut out, 0);
+        put_u64(&mut out, self.memory().words());
+
+        for reg in self.registers() {
+            put_u64(&mut out, *reg);
+        }
+        put_u32(&mut out, self.program_counter());
+        out.extend_from_slice(&self.current_instruction());
+        put_u64(&mut out, self.flags().bits());
+        put_u32(&mut out, self.stack_pointer());
+        put_u32(&mut out, self.stack_region().start);
+        put_u32(&mut out, self.stack_region().end);
+
+        let interrupts = self.interrupts();
+        put_u16(&mut out, interrupts.pending());
+        put_u8(&mut out, interrupts.enabled() as u8);
+        put_u8(&mut out, interrupts.vectors().is_some() as u8);
+        put_u32(&mut out, interrupts.vectors().unwrap_or(0));
+        put_u64(&mut out, self.cycles());
+
+        let runs: Vec<_> = self
+            .memory()
+            .chunks()
+            .into_iter()
+            .flat_map(|(base, words)| {
+                nonzero_runs(words)
+                    .into_iter()
+                    .map(move |(start, run)| (base + start as u32, run))
+            })
+            .collect();
+        put_u32(&mut out, runs.len() as u32);
+        for (start, words) in runs {
+            put_u32(&mut out, start);
+            put_u32(&mut out, words.len() as u32);
+            for word in words {
+                put_u64(&mut out, *word);
+            }
+        }
+
+        let devices = self.memory_controller().save_devices();
+        put_u32(&mut out, devices.len() as u32);
+        for (base, len, state) in devices {
+            put_u32(&mut out, base);
+            put_u32(&mut out, len);
+            put_u32(&mut out, state.len() as u32);
+            out.extend_from_slice(&state);
+        }
+        out
+    }
+
+    /// Puts the machine back the way `snapshot` found it. The snapshot is
+    /// read in full and every device state checked before anything changes.
+    pub fn restore(&mut self, bytes: &[u8]) -> Result<()> {
+        let mut reader = Reader::new(bytes);
+        if reader.take(4)? != MAGIC {
+            return Err(anyhow!("not a snapshot, bad magic number"));
+        }
+        let version = reader.u16()?;
+        if version > SNAPSHOT_VERSION {
+            return Err(anyhow!(
+                "snapshot version {} is newer than this machine's {}",
+                version,
+                SNAPSHOT_VERSION
+            ));
+        }
+        reader.u16()?;
+        let words = match version {
+            1 => reader.u32()? as u64,
+            _ => reader.u64()?,
+        };
+        let memory_words = self.memory().words();
+        if words != memory_words {
+            return Err(anyhow!(
+                "snapshot is of a {} word machine, this one has {}",
+                words,
+                memory_words
+            ));
+        }
+
+        let mut registers = [0_u64; 8];
+        for reg in registers.iter_mut() {
+            *reg = reader.u64()?;
+        }
+        let pc = reader.u32()?;
+        let instruction: [u8; 8] = reader.take(8)?.try_into().unwrap();
+        let flags = Flags::from_bits(reader.u64()?);
+        let sp = reader.u32()?;
+        let stack = reader.u32()?..reader.u32()?;
+        if stack.start > stack.end || stack.end as u64 > words {
+            return Err(anyhow!("stack {:?} does not fit in memory", stack));
+        }
+        let pending = reader.u16()?;
+        let enabled = reader.u8()? != 0;
+        let has_vectors = reader.u8()? != 0;
+        let vectors = reader.u32()?;
+        let cycles = reader.u64()?;
+
+        let mut runs = Vec::new();
+        for _ in 0..reader.u32()? {
+            let start = reader.u32()?;
+            let count = reader.u32()?;
+            if start as u64 + count as u64 > words {
+                return Err(anyhow!("memory run at {} runs past the end", start));
+            }
+            let run = (0..count)
+                .map(|_| reader.u64())
+                .collect::<Result<Vec<_>>>()?;
+            runs.push((start, run));
+        }
+
+        let mut devices = Vec::new();
+        for _ in 0..reader.u32()? {
+            let base = reader.u32()?;
+            let len = reader.u32()?;
+            let state_len = reader.u32()? as usize;
+            devices.push((base, len, reader.take(state_len)?.to_vec()));
+        }
+        if !reader.is_empty() {
+            return Err(anyhow!("trailing bytes after the device states"));
+        }
+        self.memory_controller().check_devices(&devices)?;
+
+        // nothing below fails once the devices have been checked
+        self.memory_controller_mut().restore_devices(&devices)?;
+
+        for (idx, val) in registers.into_iter().enumerate() {
+            self.set_register(idx as u8, val)?;
+        }
+        self.set_program_counter(pc);
+        self.set_current_instruction(instruction);
+        self.set_flags(flags);
+        self.set_stack_region(stack);
+        self.set_stack_pointer(sp);
+        let interrupts = self.interrupts_mut();
+        interrupts.set_pending(pending);
+        interrupts.set_enabled(enabled);
+        interrupts.set_vectors(has_vectors.then_some(vectors));
+        self.set_cycles(cycles);
+        let memory = self.memory_mut();
+        memory.clear();
+        for (start, run) in runs {
+            for (idx, word) in run.into_iter().enumerate() {
+                memory.write(start + idx as u32, word)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save_snapshot(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.snapshot()).map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+
+    pub fn restore_snapshot(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        self.restore(&bytes)
+            .map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+}
+
+/// Start address and words of each stretch of nonzero memory
+fn nonzero_runs(words: &[u64]) -> Vec<(usize, &[u64])> {
+    let mut runs = Vec::new();
+    let mut idx = 0;
+    while idx < words.len() {
+        if words[idx] == 0 {
+            idx += 1;
+            continue;
+        }
+        let len = words[idx..].iter().take_while(|word| **word != 0).count();
+        runs.push((idx, &words[idx..idx + len]));
+        idx += len;
+    }
+    runs
+}
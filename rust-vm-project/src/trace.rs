@@ -0,0 +1,458 @@
+use crate::codec::{put_u16, put_u32, put_u64, put_u8, Reader};
+use crate::disasm;
+use crate::{MemoryBackend, CPU};
+use anyhow::{anyhow, Result};
+use std::io::Write;
+use std::ops::Range;
+use std::path::Path;
+
+/// Start of a binary trace, followed by a u16 format version
+pub const MAGIC: [u8; 4] = *b"RVMT";
+const VERSION: u16 = 1;
+
+/// What one executed instruction did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub cycle: u64, // `CPU::cycles` before the instruction ran
+    pub pc: u32,
+    pub raw: u64,
+    pub text: String,              // disassembly of `raw`
+    pub registers: Vec<(u8, u64)>, // registers that changed, with their new values
+    pub writes: Vec<(u32, u64)>,   // memory writes in order, address and value
+}
+
+impl TraceRecord {
+    /// Same instruction with the same effects, the cycle count may differ
+    pub fn same_execution(&self, other: &TraceRecord) -> bool {
+        self.pc == other.pc
+            && self.raw == other.raw
+            && self.registers == other.registers
+            && self.writes == other.writes
+    }
+
+    /**
+     * One line of JSON without the newline. `raw` is a hex string, every
+     * other number is written out in full and read back exactly.
+     *
+     * ```text
+     * {"cycle":4,"pc":3,"raw":"0x0000000000070702","text":"wrt r7 7","regs":[],"writes":[[7,5]]}
+     * ```
+     */
+    pub fn to_json(&self) -> String {
+        let pairs = |pairs: Vec<String>| pairs.join(",");
+        format!(
+            "{{\"cycle\":{},\"pc\":{},\"raw\":\"{:#018x}\",\"text\":{},\"regs\":[{}],\"writes\":[{}]}}",
+            self.cycle,
+            self.pc,
+            self.raw,
+            json_string(&self.text),
+            pairs(self.registers.iter().map(|(r, v)| format!("[{},{}]", r, v)).collect()),
+            pairs(self.writes.iter().map(|(a, v)| format!("[{},{}]", a, v)).collect()),
+        )
+    }
+
+    pub fn from_json(line: &str) -> Result<Self> {
+        let mut parser = JsonParser::new(line);
+        let value = parser.value()?;
+        parser.end()?;
+
+        let raw = value.field("raw")?.as_str()?;
+        let raw = raw
+            .strip_prefix("0x")
+            .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| anyhow!("bad raw word `{}`", raw))?;
+        Ok(Self {
+            cycle: value.field("cycle")?.as_u64()?,
+            pc: value.field("pc")?.as_u32()?,
+            raw,
+            text: value.field("text")?.as_str()?.to_string(),
+            registers: value
+                .field("regs")?
+                .pairs()?
+                .into_iter()
+                .map(|(reg, val)| Ok((u8::try_from(reg)?, val)))
+                .collect::<Result<_>>()?,
+            writes: value
+                .field("writes")?
+                .pairs()?
+                .into_iter()
+                .map(|(addr, val)| Ok((u32::try_from(addr)?, val)))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// Binary form, the text is left out and rebuilt from `raw` when read
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        put_u64(&mut out, self.cycle);
+        put_u32(&mut out, self.pc);
+        put_u64(&mut out, self.raw);
+        put_u8(&mut out, self.registers.len() as u8);
+        for (reg, val) in &self.registers {
+            put_u8(&mut out, *reg);
+            put_u64(&mut out, *val);
+        }
+        put_u32(&mut out, self.writes.len() as u32);
+        for (addr, val) in &self.writes {
+            put_u32(&mut out, *addr);
+            put_u64(&mut out, *val);
+        }
+        out
+    }
+
+    fn read_from(reader: &mut Reader) -> Result<Self> {
+        let cycle = reader.u64()?;
+        let pc = reader.u32()?;
+        let raw = reader.u64()?;
+        let registers = (0..reader.u8()?)
+            .map(|_| Ok((reader.u8()?, reader.u64()?)))
+            .collect::<Result<_>>()?;
+        let writes = (0..reader.u32()?)
+            .map(|_| Ok((reader.u32()?, reader.u64()?)))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            cycle,
+            pc,
+            raw,
+            text: text(raw),
+            registers,
+            writes,
+        })
+    }
+}
+
+fn text(raw: u64) -> String {
+    disasm::disassemble_word(raw).unwrap_or_else(|| format!(".word {}", raw))
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TraceFormat {
+    Json,   // one `TraceRecord::to_json` object per line
+    Binary, // `MAGIC`, a u16 version, then `TraceRecord::to_bytes` back to back
+}
+
+/// Writes trace records to a file, stdout or anything else that's `Write`
+pub struct Tracer<W: Write> {
+    out: W,
+    format: TraceFormat,
+}
+
+impl<W: Write> Tracer<W> {
+    /// Writes the binary header straight away, JSON has none
+    pub fn new(mut out: W, format: TraceFormat) -> Result<Self> {
+        if format == TraceFormat::Binary {
+            let mut header = MAGIC.to_vec();
+            put_u16(&mut header, VERSION);
+            out.write_all(&header)?;
+        }
+        Ok(Self { out, format })
+    }
+
+    pub fn record(&mut self, record: &TraceRecord) -> Result<()> {
+        match self.format {
+            TraceFormat::Json => writeln!(self.out, "{}", record.to_json())?,
+            TraceFormat::Binary => self.out.write_all(&record.to_bytes())?,
+        }
+        Ok(())
+    }
+
+    pub fn into_inner(mut self) -> Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Reads either format back, binary traces are told apart by `MAGIC`
+pub fn read_trace(bytes: &[u8]) -> Result<Vec<TraceRecord>> {
+    if let Some(rest) = bytes.strip_prefix(&MAGIC) {
+        let mut reader = Reader::new(rest);
+        let version = reader.u16()?;
+        if version > VERSION {
+            return Err(anyhow!("trace format version {} is too new", version));
+        }
+        let mut records = Vec::new();
+        while !reader.is_empty() {
+            records.push(TraceRecord::read_from(&mut reader)?);
+        }
+        return Ok(records);
+    }
+
+    let text = std::str::from_utf8(bytes).map_err(|_| anyhow!("not a trace"))?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(idx, line)| {
+            TraceRecord::from_json(line).map_err(|e| anyhow!("line {}: {}", idx + 1, e))
+        })
+        .collect()
+}
+
+pub fn read_trace_file(path: impl AsRef<Path>) -> Result<Vec<TraceRecord>> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+    read_trace(&bytes).map_err(|e| anyhow!("{}: {}", path.display(), e))
+}
+
+/// Picks records out of a trace, every condition that is set has to hold
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TraceFilter {
+    pub pcs: Option<Range<u32>>, // executed in this range
+    pub register: Option<u8>,    // changed this register
+    pub addr: Option<u32>,       // wrote this address
+}
+
+impl TraceFilter {
+    pub fn matches(&self, record: &TraceRecord) -> bool {
+        self.pcs.as_ref().is_none_or(|pcs| pcs.contains(&record.pc))
+            && self
+                .register
+                .is_none_or(|reg| record.registers.iter().any(|(r, _)| *r == reg))
+            && self
+                .addr
+                .is_none_or(|addr| record.writes.iter().any(|(a, _)| *a == addr))
+    }
+}
+
+/// Where two traces stop agreeing, a side is `None` if that trace ended first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub index: usize,
+    pub left: Option<TraceRecord>,
+    pub right: Option<TraceRecord>,
+}
+
+/// The first record where two runs went different ways, see `TraceRecord::same_execution`
+pub fn diverge(left: &[TraceRecord], right: &[TraceRecord]) -> Option<Divergence> {
+    let index = left
+        .iter()
+        .zip(right)
+        .position(|(l, r)| !l.same_execution(r))
+        .unwrap_or_else(|| left.len().min(right.len()));
+    if index == left.len() && index == right.len() {
+        return None;
+    }
+    Some(Divergence {
+        index,
+        left: left.get(index).cloned(),
+        right: right.get(index).cloned(),
+    })
+}
+
+impl<M: MemoryBackend> CPU<M> {
+    /// `step`, writing a record of what the instruction did to `tracer`.
+    /// Faults come back as a `Fault` inside the error and aren't recorded.
+    pub fn step_traced<W: Write>(&mut self, tracer: &mut Tracer<W>) -> Result<bool> {
+        // interrupt entry pushes show up on the handler's first instruction
+        self.memory_controller_mut().start_write_log();
+        self.poll_interrupts();
+
+        let cycle = self.cycles();
+        let pc = self.program_counter();
+        let before = *self.registers();
+        let out = self.fetch_execute();
+        let writes = self.memory_controller_mut().take_write_log();
+        let out = out?;
+
+        let raw = u64::from_le_bytes(self.current_instruction());
+        let registers = before
+            .iter()
+            .zip(self.registers())
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(idx, (_, new))| (idx as u8, *new))
+            .collect();
+        tracer.record(&TraceRecord {
+            cycle,
+            pc,
+            raw,
+            text: text(raw),
+            registers,
+            writes: writes.iter().map(|w| (w.addr, w.new)).collect(),
+        })?;
+        Ok(out)
+    }
+
+    /// `run` with every instruction traced
+    pub fn run_traced<W: Write>(&mut self, tracer: &mut Tracer<W>) -> Result<()> {
+        while self.step_traced(tracer)? {}
+        Ok(())
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// just enough JSON to read back what `to_json` writes
+
+enum Json {
+    Number(u64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn field(&self, name: &str) -> Result<&Json> {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, val)| val)
+                .ok_or_else(|| anyhow!("missing field `{}`", name)),
+            _ => Err(anyhow!("expected an object")),
+        }
+    }
+
+    fn as_u64(&self) -> Result<u64> {
+        match self {
+            Json::Number(n) => Ok(*n),
+            _ => Err(anyhow!("expected a number")),
+        }
+    }
+
+    fn as_u32(&self) -> Result<u32> {
+        u32::try_from(self.as_u64()?).map_err(|_| anyhow!("number does not fit in a u32"))
+    }
+
+    fn as_str(&self) -> Result<&str> {
+        match self {
+            Json::String(s) => Ok(s),
+            _ => Err(anyhow!("expected a string")),
+        }
+    }
+
+    /// An array of two number arrays, like `regs` and `writes`
+    fn pairs(&self) -> Result<Vec<(u64, u64)>> {
+        let Json::Array(items) = self else {
+            return Err(anyhow!("expected an array"));
+        };
+        items
+            .iter()
+            .map(|item| match item {
+                Json::Array(pair) if pair.len() == 2 => Ok((pair[0].as_u64()?, pair[1].as_u64()?)),
+                _ => Err(anyhow!("expected a [number, number] pair")),
+            })
+            .collect()
+    }
+}
+
+struct JsonParser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> JsonParser<'a> {
+    fn new(src: &'a str) -> Self {
+        Self {
+            chars: src.chars().peekable(),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, want: char) -> Result<()> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(c) if c == want => Ok(()),
+            Some(c) => Err(anyhow!("expected `{}`, found `{}`", want, c)),
+            None => Err(anyhow!("expected `{}`, found the end of the line", want)),
+        }
+    }
+
+    fn end(&mut self) -> Result<()> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            None => Ok(()),
+            Some(c) => Err(anyhow!("unexpected `{}` after the record", c)),
+        }
+    }
+
+    fn value(&mut self) -> Result<Json> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('{') => self
+                .list('{', '}', |p| {
+                    let key = p.string()?;
+                    p.expect(':')?;
+                    Ok((key, p.value()?))
+                })
+                .map(Json::Object),
+            Some('[') => self.list('[', ']', |p| p.value()).map(Json::Array),
+            Some('"') => self.string().map(Json::String),
+            Some(c) if c.is_ascii_digit() => {
+                let mut digits = String::new();
+                while let Some(c) = self.chars.next_if(|c| c.is_ascii_digit()) {
+                    digits.push(c);
+                }
+                let n = digits
+                    .parse()
+                    .map_err(|_| anyhow!("`{}` does not fit in a u64", digits))?;
+                Ok(Json::Number(n))
+            }
+            Some(c) => Err(anyhow!("unexpected `{}`", c)),
+            None => Err(anyhow!("unexpected end of the line")),
+        }
+    }
+
+    /// Comma separated items between `open` and `close`
+    fn list<T>(
+        &mut self,
+        open: char,
+        close: char,
+        mut item: impl FnMut(&mut Self) -> Result<T>,
+    ) -> Result<Vec<T>> {
+        self.expect(open)?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&close).is_some() {
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => {}
+                Some(c) if c == close => return Ok(items),
+                _ => return Err(anyhow!("expected `,` or `{}`", close)),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(out),
+                Some('\\') => match self.chars.next() {
+                    Some('u') => {
+                        let hex: String = (0..4).filter_map(|_| self.chars.next()).collect();
+                        let c = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| anyhow!("bad escape `\\u{}`", hex))?;
+                        out.push(c);
+                    }
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some(c) => out.push(c),
+                    None => return Err(anyhow!("unterminated string")),
+                },
+                Some(c) => out.push(c),
+                None => return Err(anyhow!("unterminated string")),
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use crate::fault::Fault;
+use crate::{Flags, MemoryBackend, CPU};
+use std::collections::VecDeque;
+
+/// What the machine looked like before one cycle, enough to put it back
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct UndoEntry {
+    pc: u32,
+    instruction: [u8; 8],
+    flags: Flags,
+    stack_pointer: u32,
+    cycles: u64,
+    pending: u16,
+    interrupts_enabled: bool,
+    registers: Vec<(u8, u64)>, // old values of the registers the cycle wrote
+    writes: Vec<(u32, u64)>,   // old values of the RAM words it wrote, in write order
+}
+
+impl UndoEntry {
+    /// Rough heap plus inline size, what counts against the log's limit
+    fn bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.registers.len() * std::mem::size_of::<(u8, u64)>()
+            + self.writes.len() * std::mem::size_of::<(u32, u64)>()
+    }
+}
+
+/**
+ * The last however many cycles, newest at the back, for stepping backwards.
+ * Once the entries take more than `limit` bytes the oldest are dropped.
+ *
+ * Registers, the program counter, flags, stack pointer, cycle count,
+ * interrupt state and memory all rewind. Devices don't: output stays
+ * printed, input stays consumed and timers keep their count.
+ */
+#[derive(Debug, Clone)]
+pub struct UndoLog {
+    entries: VecDeque<UndoEntry>,
+    bytes: usize,
+    limit: usize,
+}
+
+impl UndoLog {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            bytes: 0,
+            limit,
+        }
+    }
+
+    /// Cycles that can be stepped back through
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Memory the log is using, counted the same way as `limit`
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Changes the bound, dropping the oldest entries if the log is over it
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.trim();
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.bytes = 0;
+    }
+
+    /// True if stepping back once would undo a write to `reg`, even one
+    /// that left it holding the same value
+    pub fn last_wrote(&self, reg: u8) -> bool {
+        self.entries
+            .back()
+            .is_some_and(|entry| entry.registers.iter().any(|(r, _)| *r == reg))
+    }
+
+    fn push(&mut self, entry: UndoEntry) {
+        self.bytes += entry.bytes();
+        self.entries.push_back(entry);
+        self.trim();
+    }
+
+    fn pop(&mut self) -> Option<UndoEntry> {
+        let entry = self.entries.pop_back()?;
+        self.bytes -= entry.bytes();
+        Some(entry)
+    }
+
+    fn trim(&mut self) {
+        while self.bytes > self.limit {
+            match self.entries.pop_front() {
+                Some(entry) => self.bytes -= entry.bytes(),
+                None => break,
+            }
+        }
+    }
+}
+
+impl Default for UndoLog {
+    /// 16 MiB, a few hundred thousand cycles of typical code
+    fn default() -> Self {
+        Self::new(16 << 20)
+    }
+}
+
+impl<M: MemoryBackend> CPU<M> {
+    /// `step`, recording how to undo it in `log`. Faulting cycles are
+    /// recorded too since they still use up cycles.
+    pub fn step_recorded(&mut self, log: &mut UndoLog) -> Result<bool, Fault> {
+        let before = *self.registers();
+        let mut entry = UndoEntry {
+            pc: self.program_counter(),
+            instruction: self.current_instruction(),
+            flags: self.flags(),
+            stack_pointer: self.stack_pointer(),
+            cycles: self.cycles(),
+            pending: self.interrupts().pending(),
+            interrupts_enabled: self.interrupts().enabled(),
+            registers: Vec::new(),
+            writes: Vec::new(),
+        };
+
+        self.take_registers_written();
+        self.memory_controller_mut().start_write_log();
+        let out = self.step();
+        let writes = self.memory_controller_mut().take_write_log();
+        let written = self.take_registers_written();
+
+        entry.registers = before
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| written & 1 << idx != 0)
+            .map(|(idx, old)| (idx as u8, *old))
+            .collect();
+        entry.writes = writes
+            .iter()
+            .filter(|w| !self.memory_controller().is_device(w.addr))
+            .map(|w| (w.addr, w.old))
+            .collect();
+        log.push(entry);
+        out
+    }
+
+    /// Undoes the newest cycle in `log`, false once there's nothing left
+    pub fn step_back(&mut self, log: &mut UndoLog) -> bool {
+        let Some(entry) = log.pop() else {
+            return false;
+        };
+        for (addr, old) in entry.writes.iter().rev() {
+            // only RAM writes are recorded and those were in range
+            let _ = self.memory_mut().write(*addr, *old);
+        }
+        for (reg, old) in entry.registers {
+            let _ = self.set_register(reg, old);
+        }
+        self.set_program_counter(entry.pc);
+        self.set_current_instruction(entry.instruction);
+        self.set_flags(entry.flags);
+        self.set_stack_pointer(entry.stack_pointer);
+        self.set_cycles(entry.cycles);
+        self.interrupts_mut().set_pending(entry.pending);
+        self.interrupts_mut().set_enabled(entry.interrupts_enabled);
+        true
+    }
+}
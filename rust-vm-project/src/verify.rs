@@ -0,0 +1,167 @@
+use crate::deserialize_u32_array;
+use crate::image::ProgramImage;
+use crate::interrupt::VECTORS;
+use crate::{Instruction, Operand};
+use std::fmt;
+
+/// One problem `verify` found, at the address of the offending word
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub addr: u32,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.addr, self.message)
+    }
+}
+
+/**
+ * Checks `image` for a machine with `memory_words` of memory without running
+ * it, every diagnostic in address order, none if it looks sound:
+ *
+ * - the entry point has to be in the code section, and both sections have
+ *   to fit in memory
+ * - every code word needs a known opcode and registers below r8
+ * - literal memory operands have to be inside memory, and `wrt` can't
+ *   target the code section since loading makes it read only
+ * - jump, branch and call targets have to be in the code section, and so do
+ *   the handlers in a `vectors` table
+ * - the last code word can't let execution fall off the end of the code
+ *
+ * Only what's in the instruction words is checked, addresses computed at run
+ * time aren't.
+ */
+pub fn verify(image: &ProgramImage, memory_words: u64) -> Vec<Diagnostic> {
+    let mut out = Vec::new();
+    let code = &image.code;
+    let mut error = |addr: u32, message: String| out.push(Diagnostic { addr, message });
+
+    if !code.contains(image.entry) {
+        error(
+            image.entry,
+            format!(
+                "entry point is outside the code section {}..{}",
+                code.base,
+                code.end()
+            ),
+        );
+    }
+
+    let limit = memory_words.min(1 << 32);
+    for (name, section) in [("code", code), ("data", &image.data)] {
+        if section.end() > limit {
+            error(
+                section.base,
+                format!(
+                    "{} section {}..{} doesn't fit in {} words of memory",
+                    name,
+                    section.base,
+                    section.end(),
+                    limit
+                ),
+            );
+        }
+    }
+    // none of the code could be loaded, and its addresses needn't fit a u32
+    if code.end() > limit {
+        out.sort_by_key(|diagnostic| diagnostic.addr);
+        return out;
+    }
+
+    for (i, &raw) in code.words.iter().enumerate() {
+        let addr = code.base + i as u32;
+        let bytes = raw.to_le_bytes();
+        let Some(instr): Option<Instruction> = num::FromPrimitive::from_u8(bytes[0]) else {
+            error(addr, format!("unknown opcode {}", bytes[0]));
+            continue;
+        };
+
+        for operand in instr.operands() {
+            match *operand {
+                Operand::Reg(idx) if bytes[idx] >= 8 => error(
+                    addr,
+                    format!("`{}` names register r{}", instr.mnemonic(), bytes[idx]),
+                ),
+                Operand::Addr(idx) => {
+                    let target = deserialize_u32_array(idx, &bytes);
+                    if target as u64 >= memory_words {
+                        error(
+                            addr,
+                            format!(
+                                "`{}` address {} is outside {} words of memory",
+                                instr.mnemonic(),
+                                target,
+                                memory_words
+                            ),
+                        );
+                    } else if instr == Instruction::WriteToMem && code.contains(target) {
+                        error(
+                            addr,
+                            format!("`wrt` to {} would write the read only code", target),
+                        );
+                    }
+                }
+                Operand::Target(idx) => {
+                    let target = deserialize_u32_array(idx, &bytes);
+                    if !code.contains(target) {
+                        error(
+                            addr,
+                            format!(
+                                "`{}` target {} is outside the code section {}..{}",
+                                instr.mnemonic(),
+                                target,
+                                code.base,
+                                code.end()
+                            ),
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if i + 1 == code.words.len() && falls_through(instr) {
+            error(
+                addr,
+                format!(
+                    "execution can run off the end of the code after `{}`",
+                    instr.mnemonic()
+                ),
+            );
+        }
+    }
+
+    if let Some(base) = image.symbol("vectors") {
+        for slot in 0..VECTORS {
+            let Some(addr) = base.checked_add(slot) else {
+                break;
+            };
+            // slots past the end of the image read as zero, no handler
+            let handler = image.data.word(addr).or_else(|| code.word(addr));
+            match handler.unwrap_or(0) {
+                0 => {}
+                handler if u32::try_from(handler).is_ok_and(|h| code.contains(h)) => {}
+                handler => error(
+                    addr,
+                    format!("vector {} handler {} is outside the code", slot, handler),
+                ),
+            }
+        }
+    }
+
+    out.sort_by_key(|diagnostic| diagnostic.addr);
+    out
+}
+
+/// True if execution can carry on to the next word after `instr`
+fn falls_through(instr: Instruction) -> bool {
+    !matches!(
+        instr,
+        Instruction::Exit
+            | Instruction::SetProgramCounter
+            | Instruction::Return
+            | Instruction::InterruptReturn
+    )
+}
@@ -0,0 +1,111 @@
+use rust_vm_project::{asm, disasm, FlatMemory, VmFault, CPU};
+
+fn run(src: &str) -> Result<CPU, VmFault> {
+    let words = asm::assemble(src).unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(128))
+        .program(&words, 0)
+        .build()?;
+    cpu.run().map_err(|fault| fault.kind)?;
+    Ok(cpu)
+}
+
+#[test]
+fn post_increment_walks_an_array() {
+    // sums the array, then copies it to `copy`
+    let cpu = run("
+            li r1 array
+            li r2 5
+    sum:    ldp r1 r3
+            add r3 r4 r4
+            icrr r5
+            cmp r5 r2
+            bne sum
+            li r1 array
+            li r6 copy
+            li r5 0
+    again:  ldp r1 r3
+            stp r3 r6
+            icrr r5
+            cmp r5 r2
+            bne again
+            ext
+    array:  .word 1
+            .word 2
+            .word 3
+            .word 4
+            .word 5
+    copy:   .word 0
+    ")
+    .unwrap();
+    assert_eq!(cpu.registers()[4], 15);
+    assert_eq!(cpu.registers()[1], 16 + 5);
+    assert_eq!(cpu.registers()[6], 21 + 5);
+    assert_eq!(&cpu.memory().data()[21..26], &[1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn offsets_follow_a_linked_list() {
+    // each node is a value then the address of the next, 0 ends the list
+    let cpu = run("
+            li r1 a
+    next:   ldr r1 r2
+            add r2 r3 r3
+            ldo r1 1 r1
+            cmp r1 r0
+            bne next
+            li r4 out
+            sto r3 r4 0
+            ext
+    a:      .word 10
+            .word b
+    b:      .word 20
+            .word c
+    c:      .word 30
+            .word 0
+    out:    .word 0
+    ")
+    .unwrap();
+    assert_eq!(cpu.registers()[3], 60);
+    assert_eq!(cpu.memory().data()[15], 60);
+}
+
+#[test]
+fn loaded_value_wins_over_the_increment() {
+    let cpu = run("
+            li r1 val
+            ldp r1 r1
+            li r2 val
+            str r1 r2
+            ext
+    val:    .word 99
+    ")
+    .unwrap();
+    assert_eq!(cpu.registers()[1], 99);
+}
+
+#[test]
+fn addresses_past_u32_or_memory_fault() {
+    assert_eq!(
+        run("lis r1 -1\nldp r1 r2\next").err(),
+        Some(VmFault::MemoryOutOfRange(u32::MAX))
+    );
+    assert_eq!(
+        run("ldo r0 -1 r2\next").err(),
+        Some(VmFault::MemoryOutOfRange(u32::MAX))
+    );
+    assert_eq!(
+        run("li r1 200\nstp r1 r1\next").err(),
+        Some(VmFault::MemoryOutOfRange(200))
+    );
+}
+
+#[test]
+fn register_addressing_disassembles() {
+    let src = "ldr r1 r2\nldo r1 -3 r2\nldp r1 r2\nstr r1 r2\nsto r1 r2 7\nstp r1 r2";
+    let words = asm::assemble(src).unwrap();
+    let text: Vec<String> = disasm::disassemble(&words, 0)
+        .into_iter()
+        .map(|line| line.text)
+        .collect();
+    assert_eq!(text.join("\n"), src);
+}
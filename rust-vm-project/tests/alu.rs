@@ -0,0 +1,123 @@
+use rust_vm_project::{asm, incode_instr, Fault, FlatMemory, Instruction, VmFault, CPU};
+
+/// Runs `op r0 r1 r2` with r0 = a and r1 = b, returns r2
+fn alu(op: &str, a: u64, b: u64) -> Result<u64, Fault> {
+    let words = asm::assemble(&format!("{} r0 r1 r2\next", op)).unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(16))
+        .program(&words, 0)
+        .register(0, a)
+        .register(1, b)
+        .register(2, 0xdead)
+        .build()
+        .unwrap();
+    cpu.run()?;
+    Ok(cpu.registers()[2])
+}
+
+fn fault(op: &str, a: u64, b: u64) -> VmFault {
+    alu(op, a, b).unwrap_err().kind
+}
+
+#[test]
+fn add() {
+    assert_eq!(alu("add", 40, 2), Ok(42));
+    assert_eq!(fault("add", u64::MAX, 1), VmFault::ArithmeticOverflow);
+}
+
+#[test]
+fn sub_subtracts() {
+    assert_eq!(alu("sub", 50, 8), Ok(42));
+    assert_eq!(alu("sub", 7, 7), Ok(0));
+    assert_eq!(fault("sub", 1, 2), VmFault::ArithmeticOverflow);
+}
+
+#[test]
+fn mul() {
+    assert_eq!(alu("mul", 6, 7), Ok(42));
+    assert_eq!(alu("mul", 6, 0), Ok(0));
+    assert_eq!(fault("mul", 1 << 32, 1 << 32), VmFault::ArithmeticOverflow);
+}
+
+#[test]
+fn div_rounds_down() {
+    assert_eq!(alu("div", 85, 2), Ok(42));
+    assert_eq!(alu("div", 1, 2), Ok(0));
+    assert_eq!(fault("div", 1, 0), VmFault::DivideByZero);
+}
+
+#[test]
+fn modulo() {
+    assert_eq!(alu("mod", 142, 100), Ok(42));
+    assert_eq!(alu("mod", 3, 7), Ok(3));
+    assert_eq!(fault("mod", 1, 0), VmFault::DivideByZero);
+}
+
+#[test]
+fn bitwise() {
+    assert_eq!(alu("and", 0b1100, 0b1010), Ok(0b1000));
+    assert_eq!(alu("or", 0b1100, 0b1010), Ok(0b1110));
+    assert_eq!(alu("xor", 0b1100, 0b1010), Ok(0b0110));
+}
+
+#[test]
+fn not_never_reads_byte_two() {
+    // byte 2 isn't one of `not`'s operands, a bad register there is ignored
+    let not = incode_instr([Instruction::Not as u8, 0, 9, 2, 0, 0, 0, 0]);
+    let ext = asm::assemble("ext").unwrap()[0];
+    let mut cpu = CPU::builder(FlatMemory::new(16))
+        .program(&[not, ext], 0)
+        .register(0, 0xff)
+        .build()
+        .unwrap();
+    cpu.run().unwrap();
+    assert_eq!(cpu.registers()[2], !0xff);
+}
+
+#[test]
+fn shifts_are_logical() {
+    assert_eq!(alu("shl", 21, 1), Ok(42));
+    assert_eq!(alu("shr", 84, 1), Ok(42));
+    assert_eq!(alu("shr", 1 << 63, 63), Ok(1));
+    assert_eq!(alu("shl", 1, 64), Ok(0));
+    assert_eq!(alu("shr", u64::MAX, 1000), Ok(0));
+}
+
+#[test]
+fn rotates_wrap_around() {
+    assert_eq!(alu("rol", 1 << 63, 1), Ok(1));
+    assert_eq!(alu("ror", 1, 1), Ok(1 << 63));
+    assert_eq!(alu("rol", 42, 64), Ok(42));
+    assert_eq!(alu("ror", 42, 65), Ok(21));
+}
+
+#[test]
+fn increment() {
+    let words = asm::assemble("icrr r0\next").unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(16))
+        .program(&words, 0)
+        .register(0, 41)
+        .build()
+        .unwrap();
+    cpu.run().unwrap();
+    assert_eq!(cpu.registers()[0], 42);
+
+    cpu.set_program_counter(0);
+    cpu.set_register(0, u64::MAX).unwrap();
+    assert_eq!(cpu.run().unwrap_err().kind, VmFault::ArithmeticOverflow);
+}
+
+#[test]
+fn fault_leaves_destination_alone() {
+    let words = asm::assemble("div r0 r1 r2\next").unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(16))
+        .program(&words, 0)
+        .register(0, 1)
+        .register(2, 0xdead)
+        .build()
+        .unwrap();
+    let err = cpu.run().unwrap_err();
+    assert_eq!(err.pc, 0);
+    assert_eq!(err.instruction[0], rust_vm_project::Instruction::Div as u8);
+    assert_eq!(cpu.registers()[2], 0xdead);
+    assert_eq!(cpu.program_counter(), 0);
+}
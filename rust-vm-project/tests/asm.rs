@@ -0,0 +1,88 @@
+use rust_vm_project::{asm, Instruction};
+
+fn err(src: &str) -> String {
+    asm::assemble(src).unwrap_err().to_string()
+}
+
+#[test]
+fn instructions_encode_their_operands() {
+    let words = asm::assemble("icrr r3\nspc 0x10\nlod one r1\none: .word 7").unwrap();
+    assert_eq!(
+        words,
+        vec![
+            Instruction::IncrementReg as u64 | 3 << 8,
+            Instruction::SetProgramCounter as u64 | 0x10 << 8,
+            Instruction::LoadFromMem as u64 | 3 << 8 | 1 << 40,
+            7,
+        ]
+    );
+    // a bare number is a register too, and commas separate like spaces
+    assert_eq!(
+        asm::assemble("add 1, r2, 3").unwrap(),
+        asm::assemble("add r1 r2 r3").unwrap()
+    );
+}
+
+#[test]
+fn numbers_parse_in_decimal_hex_and_negative() {
+    let words = asm::assemble(".word 42\n.word 0xff\n.word -1\n.word -0x10").unwrap();
+    assert_eq!(words, vec![42, 255, u64::MAX, (-16_i64) as u64]);
+    assert_eq!(
+        asm::assemble("lis r1 -5").unwrap()[0],
+        Instruction::LoadSignedImm as u64 | 1 << 8 | (-5_i32 as u32 as u64) << 16
+    );
+    assert!(err("li r1 0x100000000").contains("does not fit in a u32"));
+    assert!(err("lis r1 -2147483649").contains("does not fit in an i32"));
+    assert!(err(".word 12ab").contains("expected a number or label"));
+}
+
+#[test]
+fn labels_resolve_forwards_and_backwards() {
+    let src = "
+    top:    icrr r1         ; a comment
+            ieqe r1 r2 end
+            spc top
+    end:    ext
+    ";
+    let words = asm::assemble(src).unwrap();
+    assert_eq!(words[1] >> 24 & 0xffff_ffff, 3);
+    assert_eq!(words[2] >> 8 & 0xffff_ffff, 0);
+}
+
+#[test]
+fn sections_and_entry_points() {
+    let src = "
+            .data
+    a:      .word 1
+            .code
+    first:  ext
+    second: ext
+            .data
+    b:      .word 2
+            .entry second
+    ";
+    let image = asm::assemble_image(src).unwrap();
+    assert_eq!(image.code.words.len(), 2);
+    assert_eq!((image.data.base, image.data.words.clone()), (2, vec![1, 2]));
+    assert_eq!(image.symbol("a"), Some(2));
+    assert_eq!(image.symbol("b"), Some(3));
+    assert_eq!(image.entry, 1);
+
+    assert_eq!(asm::assemble_image("ext\nstart: ext").unwrap().entry, 1);
+    assert_eq!(asm::assemble_image("ext\next").unwrap().entry, 0);
+}
+
+#[test]
+fn mistakes_are_reported_with_their_line() {
+    assert_eq!(err("ext\nfrob r1"), "line 2: unknown mnemonic `frob`");
+    assert_eq!(
+        err("spc nowhere"),
+        "line 1: expected a number or label, got `nowhere`"
+    );
+    assert_eq!(err("a: ext\na: ext"), "line 2: label `a` defined twice");
+    assert_eq!(err("icrr r8"), "line 1: bad register `r8`, expected r0..r7");
+    assert_eq!(err("icrr"), "line 1: `icrr` takes 1 operand(s), 0 given");
+    assert_eq!(err(".bss"), "line 1: unknown directive `.bss`");
+    assert_eq!(err(".word"), "line 1: `.word` needs a value");
+    assert_eq!(err("1x: ext"), "line 1: bad label `1x`");
+}
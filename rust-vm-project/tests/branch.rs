@@ -0,0 +1,86 @@
+use rust_vm_project::{asm, Flags, FlatMemory, CPU};
+
+/// Compares a with b and reports whether `branch` was taken
+fn taken(branch: &str, a: u64, b: u64) -> bool {
+    let src = format!(
+        "
+                cmp r0 r1
+                {} yes
+                ext
+        yes:    icrr r7
+                ext
+        ",
+        branch
+    );
+    let words = asm::assemble(&src).unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(16))
+        .program(&words, 0)
+        .register(0, a)
+        .register(1, b)
+        .build()
+        .unwrap();
+    cpu.run().unwrap();
+    cpu.registers()[7] == 1
+}
+
+const MINUS_ONE: u64 = -1_i64 as u64;
+
+#[test]
+fn equality() {
+    assert!(taken("beq", 3, 3));
+    assert!(!taken("beq", 3, 4));
+    assert!(taken("bne", 3, 4));
+    assert!(!taken("bne", 3, 3));
+}
+
+#[test]
+fn signed() {
+    assert!(taken("blt", MINUS_ONE, 1));
+    assert!(!taken("blt", 1, MINUS_ONE));
+    assert!(taken("ble", 2, 2));
+    assert!(taken("bgt", 1, MINUS_ONE));
+    assert!(!taken("bgt", 2, 2));
+    assert!(taken("bge", 2, 2));
+    assert!(taken("blt", i64::MIN as u64, 1));
+    assert!(taken("bgt", i64::MAX as u64, MINUS_ONE));
+}
+
+#[test]
+fn unsigned() {
+    assert!(!taken("bltu", MINUS_ONE, 1));
+    assert!(taken("bltu", 1, MINUS_ONE));
+    assert!(taken("bleu", 2, 2));
+    assert!(taken("bgtu", MINUS_ONE, 1));
+    assert!(!taken("bgtu", 2, 2));
+    assert!(taken("bgeu", 2, 2));
+    assert!(!taken("bgeu", 1, 2));
+}
+
+#[test]
+fn alu_sets_flags() {
+    let words = asm::assemble("sub r0 r0 r1\nshl r2 r3 r4\next").unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(16))
+        .program(&words, 0)
+        .register(0, 5)
+        .register(2, 1 << 63)
+        .register(3, 1)
+        .build()
+        .unwrap();
+    cpu.step().unwrap();
+    assert_eq!(
+        cpu.flags(),
+        Flags {
+            zero: true,
+            ..Flags::default()
+        }
+    );
+    cpu.step().unwrap();
+    assert_eq!(
+        cpu.flags(),
+        Flags {
+            zero: true,
+            carry: true,
+            ..Flags::default()
+        }
+    );
+}
@@ -0,0 +1,109 @@
+use rust_vm_project::{asm, FlatMemory, VmFault, CPU};
+
+fn run(src: &str) -> Result<CPU, VmFault> {
+    let words = asm::assemble(src).unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(64))
+        .program(&words, 0)
+        .build()?;
+    cpu.run().map_err(|fault| fault.kind)?;
+    Ok(cpu)
+}
+
+#[test]
+fn strlen_walks_bytes() {
+    // "hello, world" packed little endian, 8 bytes to a word
+    let text = b"hello, world\0\0\0\0";
+    let mut src = String::from(
+        "
+            li r1 text
+            li r2 8
+            mul r1 r2 r1
+    next:   lbu r1 0 r3
+            cmp r3 r0
+            beq done
+            icrr r1
+            icrr r4
+            spc next
+    done:   ext
+    text:",
+    );
+    for chunk in text.chunks(8) {
+        let word = u64::from_le_bytes(chunk.try_into().unwrap());
+        src.push_str(&format!("\n .word {}", word));
+    }
+    let cpu = run(&src).unwrap();
+    assert_eq!(cpu.registers()[4], 12);
+}
+
+#[test]
+fn loads_extend_by_sign_or_zero() {
+    let cpu = run("
+            li r1 64
+            lb r1 0 r2
+            lbu r1 0 r3
+            lh r1 2 r4
+            lhu r1 2 r5
+            lw r1 4 r6
+            lwu r1 4 r7
+            ext
+            .word 0x80000000fffe00ff
+    ")
+    .unwrap();
+    let regs = cpu.registers();
+    assert_eq!(regs[2], u64::MAX);
+    assert_eq!(regs[3], 0xff);
+    assert_eq!(regs[4] as i64, -2);
+    assert_eq!(regs[5], 0xfffe);
+    assert_eq!(regs[6] as i64, i32::MIN as i64);
+    assert_eq!(regs[7], 0x8000_0000);
+}
+
+#[test]
+fn stores_only_touch_their_bytes() {
+    let cpu = run("
+            li r1 48
+            lis r2 -1
+            li r3 0x1234
+            sb r2 r1 1
+            sh r3 r1 6
+            sw r2 r1 12
+            ext
+    ")
+    .unwrap();
+    assert_eq!(cpu.memory().data()[6], 0x1234_0000_0000_ff00);
+    assert_eq!(cpu.memory().data()[7], 0xffff_ffff_0000_0000);
+}
+
+#[test]
+fn byte_order_matches_instruction_encoding() {
+    // the first byte of an instruction word is its opcode
+    let cpu = run("
+            lbu r0 0 r1
+            lbu r0 8 r2
+            ext
+    ")
+    .unwrap();
+    let words = asm::assemble("lbu r0 0 r1\nlbu r0 8 r2").unwrap();
+    assert_eq!(cpu.registers()[1], words[0] & 0xff);
+    assert_eq!(cpu.registers()[2], words[1] & 0xff);
+}
+
+#[test]
+fn unaligned_or_distant_accesses_fault() {
+    assert_eq!(
+        run("li r1 3\nlh r1 0 r2\next").err(),
+        Some(VmFault::Misaligned(3))
+    );
+    assert_eq!(
+        run("li r1 2\nsw r1 r1 0\next").err(),
+        Some(VmFault::Misaligned(2))
+    );
+    assert_eq!(
+        run("lb r0 -1 r2\next").err(),
+        Some(VmFault::MemoryOutOfRange(u32::MAX))
+    );
+    assert_eq!(
+        run("li r1 512\nsb r1 r1 0\next").err(),
+        Some(VmFault::MemoryOutOfRange(64))
+    );
+}
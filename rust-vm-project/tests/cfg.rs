@@ -0,0 +1,120 @@
+use rust_vm_project::asm;
+use rust_vm_project::cfg::{Cfg, Edge, EdgeKind};
+use rust_vm_project::image::{ProgramImage, Section};
+use rust_vm_project::Instruction;
+
+const FIB: &str = "
+    start:  li r0 0
+            li r1 1
+            li r2 10
+    loop:   add r0 r1 r7
+            rw r1 r0
+            rw r7 r1
+            icrr r3
+            ieqe r2 r3 done
+            spc loop
+    dead:   icrr r4
+    done:   wrt r7 result
+            ext
+            .data
+    result: .word 0
+";
+
+#[test]
+fn fib_splits_into_blocks() {
+    let cfg = Cfg::build(&asm::assemble_image(FIB).unwrap());
+    let starts: Vec<u32> = cfg.blocks().map(|block| block.start).collect();
+    assert_eq!(starts, vec![0, 3, 8, 9, 10]);
+
+    let body = cfg.block(5).unwrap();
+    assert_eq!((body.start, body.end()), (3, 8));
+    assert_eq!(
+        body.edges,
+        vec![
+            Edge {
+                to: 10,
+                kind: EdgeKind::Branch
+            },
+            Edge {
+                to: 8,
+                kind: EdgeKind::FallThrough
+            },
+        ]
+    );
+    assert_eq!(cfg.block(8).unwrap().edges[0].kind, EdgeKind::Jump);
+    assert!(cfg.block(10).unwrap().edges.is_empty());
+    assert!(cfg.block(12).is_none());
+}
+
+#[test]
+fn fib_has_one_loop_and_dead_code() {
+    let cfg = Cfg::build(&asm::assemble_image(FIB).unwrap());
+    assert_eq!(cfg.unreachable(), vec![9]);
+
+    let loops = cfg.loops();
+    assert_eq!(loops.len(), 1);
+    assert_eq!(loops[0].header, 3);
+    assert_eq!(loops[0].latches, vec![8]);
+    assert_eq!(loops[0].body, vec![3, 8]);
+}
+
+#[test]
+fn calls_and_handlers_are_followed() {
+    let src = "
+    start:  call f
+            ext
+    f:      ret
+    tick:   iret
+    unused: ext
+            .data
+    vectors: .word 0
+             .word tick
+    ";
+    let cfg = Cfg::build(&asm::assemble_image(src).unwrap());
+    assert_eq!(cfg.roots(), &[0, 3]);
+    let kinds: Vec<EdgeKind> = cfg.block(0).unwrap().edges.iter().map(|e| e.kind).collect();
+    assert_eq!(kinds, vec![EdgeKind::Call, EdgeKind::FallThrough]);
+    assert_eq!(cfg.unreachable(), vec![4]);
+    assert!(cfg.loops().is_empty());
+}
+
+#[test]
+fn nested_loops_share_blocks() {
+    let src = "
+    outer:  icrr r1
+    inner:  icrr r2
+            ieqe r2 r3 inner
+            ieqe r1 r3 outer
+            ext
+    ";
+    let cfg = Cfg::build(&asm::assemble_image(src).unwrap());
+    let loops = cfg.loops();
+    assert_eq!(loops.len(), 2);
+    assert_eq!((loops[0].header, loops[0].body.clone()), (0, vec![0, 1, 3]));
+    assert_eq!((loops[1].header, loops[1].body.clone()), (1, vec![1]));
+}
+
+#[test]
+fn dot_lists_blocks_and_edges() {
+    let image = asm::assemble_image(FIB).unwrap();
+    let dot = Cfg::build(&image).to_dot(&image.symbols);
+    assert!(dot.starts_with("digraph cfg {"));
+    assert!(dot.contains("b3 [label=\"loop:\\l     3: add r0 r1 r7\\l"));
+    assert!(dot.contains("b3 -> b10 [label=\"taken\"];"));
+    assert!(dot.contains("b8 -> b3 [penwidth=2];"));
+    assert!(dot.contains("b9 [label=\"dead:\\l     9: icrr r4\\l\", color=gray"));
+    assert!(dot.trim_end().ends_with('}'));
+}
+
+#[test]
+fn code_past_the_address_space_is_cut_off() {
+    let code = Section {
+        base: u32::MAX,
+        words: vec![Instruction::IncrementReg as u64 | 1 << 8; 3],
+    };
+    let cfg = Cfg::build(&ProgramImage::new(u32::MAX, code, Section::default()));
+    let blocks: Vec<_> = cfg.blocks().collect();
+    assert_eq!(blocks.len(), 1);
+    assert_eq!((blocks[0].start, blocks[0].end()), (u32::MAX, 1 << 32));
+    assert!(blocks[0].edges.is_empty());
+}
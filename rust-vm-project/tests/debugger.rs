@@ -0,0 +1,139 @@
+use rust_vm_project::asm;
+use rust_vm_project::debugger::Debugger;
+use rust_vm_project::{FlatMemory, PagedMemory, CPU};
+
+const COUNT: &str = "
+    start:  li r1 3
+    loop:   icrr r2
+            cmp r2 r1
+            bne loop
+            wrt r2 out
+            ext
+            .data
+    out:    .word 0
+";
+
+fn debugger() -> Debugger<FlatMemory> {
+    let image = asm::assemble_image(COUNT).unwrap();
+    let cpu = CPU::builder(FlatMemory::new(64))
+        .image(&image)
+        .build()
+        .unwrap();
+    Debugger::new(cpu).with_symbols(image.symbols)
+}
+
+/// Runs `script` without prompts, returning everything the debugger printed
+fn script(debugger: &mut Debugger<FlatMemory>, script: &str) -> String {
+    let mut out = Vec::new();
+    debugger.repl(script.as_bytes(), &mut out, false).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn scripts_drive_breakpoints_stepping_and_memory() {
+    let mut debugger = debugger();
+    let out = script(
+        &mut debugger,
+        "
+        break loop      # labels work as addresses
+        continue
+        step 2
+        set r1 5
+        w out 9
+        x out 2
+        delete loop
+        breaks
+        continue
+        x out
+        quit
+        step
+        ",
+    );
+    let expected = "\
+(rvm) break loop      # labels work as addresses
+breakpoint at 1
+(rvm) continue
+breakpoint at 1
+loop:
+=>*     1: 0a 02 00 00 00 00 00 00  icrr r2
+(rvm) step 2
+=>      3: 18 01 00 00 00 00 00 00  bne 1
+(rvm) set r1 5
+(rvm) w out 9
+(rvm) x out 2
+     6: 9
+     7: 0
+(rvm) delete loop
+(rvm) breaks
+(rvm) continue
+program exited
+out:
+=>      6: 05 00 00 00 00 00 00 00  spc 0
+(rvm) x out
+     6: 5
+(rvm) quit
+";
+    assert_eq!(out, expected);
+
+    // the loop ran to the new bound and nothing after `quit` ran
+    assert!(debugger.exited());
+    assert_eq!(debugger.cpu().registers()[1..3], [5, 5]);
+    assert_eq!(debugger.breakpoints().count(), 0);
+}
+
+#[test]
+fn bad_commands_report_errors_and_carry_on() {
+    let mut debugger = debugger();
+    let out = script(
+        &mut debugger,
+        "frob\nset r9 1\nx 0x100000000\ndelete 3\nset pc loop\nstep\n",
+    );
+    assert!(out.contains("error: unknown command `frob`, try `help`"));
+    assert!(out.contains("error: bad register `r9`, expected r0..r7, pc or sp"));
+    assert!(out.contains("error: `0x100000000` does not fit in a u32"));
+    assert!(out.contains("error: no breakpoint at 3"));
+    assert_eq!(debugger.cpu().program_counter(), 2);
+    assert_eq!(debugger.cpu().registers()[2], 1);
+}
+
+#[test]
+fn writes_can_patch_read_only_code() {
+    let mut debugger = debugger();
+    // turn the icrr at `loop` into an ext
+    let out = script(&mut debugger, "w loop 0\nx loop\ncontinue\n");
+    assert!(!out.contains("error"), "{}", out);
+    assert!(out.contains("     1: 0\n"));
+    assert!(out.contains("program exited"));
+    // exited straight away, past the patched word
+    assert_eq!(debugger.cpu().program_counter(), 2);
+    assert_eq!(debugger.cpu().registers()[2], 0);
+
+    // the program itself still can't
+    let fault = debugger.cpu_mut().memory_controller_mut().write(1, 0);
+    assert!(fault.is_err());
+}
+
+#[test]
+fn listings_reach_the_top_of_the_address_space() {
+    let cpu = CPU::builder(PagedMemory::new())
+        .program(&asm::assemble("ext").unwrap(), u32::MAX as usize)
+        .entry(u32::MAX)
+        .build()
+        .unwrap();
+    let mut debugger = Debugger::new(cpu);
+    let mut out = Vec::new();
+    debugger.command("l 1", &mut out).unwrap();
+    debugger.command("l", &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(out.matches("4294967295: 00").count(), 2, "{}", out);
+    assert!(out.contains("4294967293: "));
+}
+
+#[test]
+fn listings_stop_at_the_end_of_flat_memory() {
+    let mut debugger = debugger();
+    script(&mut debugger, "set pc 63\n");
+    let out = script(&mut debugger, "l\n");
+    assert!(out.contains("=>     63: "));
+    assert!(!out.contains("64: "));
+}
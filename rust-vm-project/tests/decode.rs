@@ -0,0 +1,63 @@
+use rust_vm_project::device::ConsoleIn;
+use rust_vm_project::{asm, FlatMemory, VmFault, CPU};
+
+/// Runs `patch` once, overwrites it with the word in r0 and runs it again
+const PATCH: &str = "
+    patch:  icrr r1
+            icrr r3
+            wrt r0 patch
+            lod two r4
+            ieqe r3 r4 done
+            spc patch
+    done:   ext
+    two:    .word 2
+";
+
+fn machine(cached: bool) -> CPU {
+    let words = asm::assemble(PATCH).unwrap();
+    let patched = asm::assemble("icrr r2").unwrap()[0];
+    let mut cpu = CPU::builder(FlatMemory::new(64))
+        .program(&words, 0)
+        .register(0, patched)
+        .build()
+        .unwrap();
+    cpu.memory_controller_mut().set_decode_cache(cached);
+    cpu
+}
+
+#[test]
+fn writes_to_code_invalidate_the_cache() {
+    for cached in [true, false] {
+        let mut cpu = machine(cached);
+        assert_eq!(cpu.memory_controller().decode_cache(), cached);
+        cpu.run().unwrap();
+        assert_eq!(&cpu.registers()[1..4], &[1, 1, 2]);
+    }
+}
+
+#[test]
+fn backend_writes_invalidate_the_cache() {
+    let mut cpu = machine(true);
+    cpu.step().unwrap();
+    cpu.set_program_counter(0);
+    cpu.memory_mut().data_mut()[0] = asm::assemble("icrr r5").unwrap()[0];
+    cpu.step().unwrap();
+    assert_eq!(cpu.registers()[1], 1);
+    assert_eq!(cpu.registers()[5], 1);
+    assert_eq!(
+        u64::from_le_bytes(cpu.current_instruction()),
+        cpu.memory().data()[0]
+    );
+}
+
+#[test]
+fn mapping_a_device_invalidates_the_cache() {
+    let mut cpu = machine(true);
+    cpu.step().unwrap();
+    cpu.set_program_counter(0);
+    // an empty console reads all ones, not an instruction
+    cpu.memory_controller_mut()
+        .map_device(0, 1, Box::new(ConsoleIn::from_buffer(Vec::new())));
+    let fault = cpu.step().err().map(|fault| fault.kind);
+    assert_eq!(fault, Some(VmFault::InvalidOpcode(0xff)));
+}
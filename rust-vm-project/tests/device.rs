@@ -0,0 +1,71 @@
+use rust_vm_project::device::{ConsoleIn, ConsoleOut, HaltPort, CONSOLE_IN, CONSOLE_OUT, HALT};
+use rust_vm_project::{asm, FlatMemory, CPU};
+
+#[test]
+fn echo_until_end_of_input_then_halt() {
+    let (out, buffer) = ConsoleOut::buffered();
+    let words = asm::assemble(&format!(
+        "
+    loop:   lod {in} r0
+            cmp r0 r1
+            beq done
+            wrt r0 {out}
+            spc loop
+    done:   lod three r2
+            wrt r2 {halt}
+            clr r3
+            ext
+    three:  .word 3
+        ",
+        in = CONSOLE_IN,
+        out = CONSOLE_OUT,
+        halt = HALT
+    ))
+    .unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(128))
+        .program(&words, 0)
+        .register(1, u64::MAX)
+        .register(3, 7)
+        .device(CONSOLE_OUT, 2, Box::new(out))
+        .device(
+            CONSOLE_IN,
+            1,
+            Box::new(ConsoleIn::from_buffer(b"hi".to_vec())),
+        )
+        .device(HALT, 1, Box::new(HaltPort::default()))
+        .build()
+        .unwrap();
+    cpu.run().unwrap();
+
+    assert_eq!(&*buffer.borrow(), b"hi");
+    assert_eq!(cpu.exit_code(), Some(3));
+    // the halt port stops the machine before the instructions after it
+    assert_eq!(cpu.registers()[3], 7);
+}
+
+#[test]
+fn numbers_and_plain_ram_still_work() {
+    let (out, buffer) = ConsoleOut::buffered();
+    let words = asm::assemble(&format!(
+        "
+            lod n r0
+            wrt r0 {}
+            wrt r0 100
+            lod 100 r1
+            ext
+    n:      .word 42
+        ",
+        CONSOLE_OUT + 1
+    ))
+    .unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(128))
+        .program(&words, 0)
+        .device(CONSOLE_OUT, 2, Box::new(out))
+        .build()
+        .unwrap();
+    cpu.run().unwrap();
+
+    assert_eq!(&*buffer.borrow(), b"42\n");
+    assert_eq!(cpu.registers()[1], 42);
+    assert_eq!(cpu.exit_code(), None);
+}
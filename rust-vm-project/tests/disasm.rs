@@ -0,0 +1,20 @@
+use rust_vm_project::{asm, disasm};
+
+#[test]
+fn listings_round_trip_through_the_assembler() {
+    let src = "icrr r1\nieqe r1 r2 3\nlis r3 -7\next";
+    let words = asm::assemble(src).unwrap();
+    let text: Vec<String> = disasm::disassemble(&words, 0)
+        .into_iter()
+        .map(|line| line.text)
+        .collect();
+    assert_eq!(asm::assemble(&text.join("\n")).unwrap(), words);
+}
+
+#[test]
+fn listings_stop_at_the_end_of_the_address_space() {
+    let lines = disasm::disassemble(&[0, 0, 0], u32::MAX - 1);
+    let addrs: Vec<u32> = lines.iter().map(|line| line.addr).collect();
+    assert_eq!(addrs, vec![u32::MAX - 1, u32::MAX]);
+    assert_eq!(disasm::data_listing(&[1, 2], u32::MAX).len(), 1);
+}
@@ -0,0 +1,66 @@
+use rust_vm_project::{asm, incode_instr, Fault, FlatMemory, Instruction, VmFault, CPU};
+
+/// Runs `icrr r0` then `bad`, on a machine with 16 words of memory
+fn run(bad: u64) -> Result<(), Fault> {
+    let icrr = asm::assemble("icrr r0").unwrap()[0];
+    let ext = asm::assemble("ext").unwrap()[0];
+    let mut cpu = CPU::builder(FlatMemory::new(16))
+        .program(&[icrr, bad, ext], 0)
+        .build()
+        .unwrap();
+    let out = cpu.run();
+    // the machine stops on the faulting word, the instruction before it done
+    assert_eq!(cpu.program_counter(), 1);
+    assert_eq!(cpu.registers()[0], 1);
+    out
+}
+
+#[test]
+fn bad_registers_fault() {
+    let bytes = [Instruction::Add as u8, 9, 1, 2, 0, 0, 0, 0];
+    assert_eq!(
+        run(incode_instr(bytes)),
+        Err(Fault {
+            kind: VmFault::BadRegister(9),
+            pc: 1,
+            instruction: bytes,
+        })
+    );
+}
+
+#[test]
+fn literal_addresses_past_memory_fault() {
+    let lod = asm::assemble("lod 100 r1").unwrap()[0];
+    assert_eq!(
+        run(lod),
+        Err(Fault {
+            kind: VmFault::MemoryOutOfRange(100),
+            pc: 1,
+            instruction: lod.to_le_bytes(),
+        })
+    );
+
+    let wrt = asm::assemble("wrt r1 16").unwrap()[0];
+    let fault = run(wrt).unwrap_err();
+    assert_eq!(fault.kind, VmFault::MemoryOutOfRange(16));
+    assert_eq!(
+        fault.to_string(),
+        format!(
+            "[DEATH]: memory address 16 out of range at pc 1 executing {:?}",
+            wrt.to_le_bytes()
+        )
+    );
+}
+
+#[test]
+fn invalid_opcodes_fault() {
+    let bytes = [0xff, 1, 2, 3, 4, 5, 6, 7];
+    assert_eq!(
+        run(u64::from_le_bytes(bytes)),
+        Err(Fault {
+            kind: VmFault::InvalidOpcode(0xff),
+            pc: 1,
+            instruction: bytes,
+        })
+    );
+}
@@ -0,0 +1,66 @@
+use rust_vm_project::{asm, CostTable, FlatMemory, Instruction, RunOutcome, CPU};
+
+#[test]
+fn jump_to_itself_runs_out_of_fuel() {
+    let words = asm::assemble("hang: spc hang").unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(64))
+        .program(&words, 0)
+        .build()
+        .unwrap();
+
+    let outcome = cpu.run_with_budget(1000).unwrap();
+    assert_eq!(outcome, RunOutcome::OutOfFuel { cycles: 1000 });
+    assert_eq!(cpu.cycles(), 1000);
+    assert_eq!(cpu.program_counter(), 0);
+}
+
+#[test]
+fn costs_are_charged_per_opcode() {
+    let words = asm::assemble(
+        "
+            icrr r0
+            mul r0 r0 r1
+            mul r1 r1 r1
+            ext
+        ",
+    )
+    .unwrap();
+    let costs = CostTable::default().with(Instruction::Mul, 10);
+    let mut cpu = CPU::builder(FlatMemory::new(64))
+        .program(&words, 0)
+        .costs(costs)
+        .build()
+        .unwrap();
+
+    assert_eq!(
+        cpu.run_with_budget(100).unwrap(),
+        RunOutcome::Exited { cycles: 22 }
+    );
+}
+
+#[test]
+fn the_last_instruction_can_overshoot_and_runs_resume() {
+    let words = asm::assemble(
+        "
+            mul r0 r0 r0
+            mul r0 r0 r0
+            ext
+        ",
+    )
+    .unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(64))
+        .program(&words, 0)
+        .costs(CostTable::uniform(5))
+        .build()
+        .unwrap();
+
+    assert_eq!(
+        cpu.run_with_budget(3).unwrap(),
+        RunOutcome::OutOfFuel { cycles: 5 }
+    );
+    assert_eq!(cpu.program_counter(), 1);
+    assert_eq!(
+        cpu.run_with_budget(100).unwrap(),
+        RunOutcome::Exited { cycles: 10 }
+    );
+}
@@ -0,0 +1,82 @@
+use rust_vm_project::{asm, FlatMemory, VmFault, CPU};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const LOG: u32 = 1;
+const CONFIG: u32 = 2;
+
+fn machine(src: &str) -> CPU {
+    let words = asm::assemble(src).unwrap();
+    CPU::builder(FlatMemory::new(64))
+        .program(&words, 0)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn host_functions_see_registers_and_memory() {
+    let logged = Rc::new(RefCell::new(Vec::new()));
+    let sink = logged.clone();
+    let words = asm::assemble(
+        "
+            li r1 7
+            hostcall 2
+            hostcall 1
+            wrt r0 out
+            li r1 9
+            hostcall 2
+            hostcall 1
+            ext
+    out:    .word 0
+    ",
+    )
+    .unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(64))
+        .program(&words, 0)
+        .hostcall(LOG, move |cpu| {
+            sink.borrow_mut().push(cpu.registers()[0]);
+            Ok(())
+        })
+        .build()
+        .unwrap();
+    // the config lookup answers in r0 and counts its calls in memory
+    cpu.register_hostcall(CONFIG, |cpu| {
+        let key = cpu.registers()[1];
+        cpu.set_register(0, key * 100)?;
+        let calls = cpu.memory_controller_mut().read(60)?;
+        cpu.memory_controller_mut().write(60, calls + 1)
+    });
+    cpu.run().unwrap();
+
+    assert_eq!(*logged.borrow(), [700, 900]);
+    assert_eq!(cpu.memory().data()[8], 700);
+    assert_eq!(cpu.memory().data()[60], 2);
+}
+
+#[test]
+fn unknown_ids_fault() {
+    let mut cpu = machine("hostcall 5\next");
+    let fault = cpu.run().unwrap_err();
+    assert_eq!(fault.kind, VmFault::UnknownHostCall(5));
+    assert_eq!(fault.pc, 0);
+
+    cpu.register_hostcall(5, |_| Ok(()));
+    assert!(cpu.has_hostcall(5));
+    cpu.run().unwrap();
+    assert!(cpu.unregister_hostcall(5));
+    assert!(!cpu.has_hostcall(5));
+}
+
+#[test]
+fn host_errors_fault_the_hostcall() {
+    let mut cpu = machine("icrr r0\nhostcall 0\next");
+    cpu.register_hostcall(0, |cpu| {
+        cpu.set_register(0, 42)?;
+        Err(VmFault::DivideByZero)
+    });
+    let fault = cpu.run().unwrap_err();
+    assert_eq!(fault.kind, VmFault::DivideByZero);
+    assert_eq!(fault.pc, 1);
+    // still registered after failing
+    assert!(cpu.has_hostcall(0));
+}
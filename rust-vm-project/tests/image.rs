@@ -0,0 +1,104 @@
+use rust_vm_project::asm;
+use rust_vm_project::image::{ProgramImage, Section, Symbol, ISA_VERSION};
+
+const SRC: &str = "
+    start:  lod one r1
+            icrr r1
+            ext
+            .data
+    one:    .word 1
+";
+
+fn section(base: u32, len: usize) -> Section {
+    Section {
+        base,
+        words: vec![0; len],
+    }
+}
+
+fn err(bytes: &[u8]) -> String {
+    ProgramImage::from_bytes(bytes).unwrap_err().to_string()
+}
+
+#[test]
+fn images_round_trip_with_their_symbols() {
+    let image = asm::assemble_image(SRC).unwrap();
+    assert_eq!(image.symbol("one"), Some(3));
+    let bytes = image.to_bytes().unwrap();
+    assert_eq!(&bytes[..4], b"RVMI");
+    assert_eq!(ProgramImage::from_bytes(&bytes).unwrap(), image);
+}
+
+#[test]
+fn only_addressable_sections_are_written() {
+    let image = ProgramImage::new(u32::MAX, section(u32::MAX, 2), Section::default());
+    let err = image.to_bytes().unwrap_err().to_string();
+    assert!(err.contains("code section of 2 words at 4294967295 runs past"));
+
+    // the very last word is fine
+    let image = ProgramImage::new(u32::MAX, section(u32::MAX, 1), Section::default());
+    let bytes = image.to_bytes().unwrap();
+    assert_eq!(ProgramImage::from_bytes(&bytes).unwrap(), image);
+}
+
+#[test]
+fn malformed_bytes_are_refused() {
+    let bytes = asm::assemble_image(SRC).unwrap().to_bytes().unwrap();
+
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] = b'X';
+    assert!(err(&bad_magic).contains("bad magic"));
+
+    let mut newer = bytes.clone();
+    newer[4..6].copy_from_slice(&(ISA_VERSION + 1).to_le_bytes());
+    assert!(err(&newer).contains("isa version"));
+
+    assert!(err(&bytes[..bytes.len() - 1]).contains("truncated"));
+    assert!(err(&bytes[..10]).contains("truncated"));
+
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert!(err(&trailing).contains("trailing bytes"));
+}
+
+#[test]
+fn sections_past_the_address_space_are_refused() {
+    let mut image = ProgramImage::new(0, section(0, 1), Section::default());
+    image.code.base = u32::MAX;
+    let mut bytes = image.to_bytes().unwrap();
+    // claim two words without writing the second, the range is checked first
+    bytes[16..20].copy_from_slice(&2_u32.to_le_bytes());
+    assert!(err(&bytes).contains("past the end of the address space"));
+}
+
+#[test]
+fn overlong_symbol_names_are_refused() {
+    let mut image = ProgramImage::new(0, section(0, 1), Section::default());
+    image.symbols.push(Symbol {
+        name: "x".repeat(65536),
+        addr: 0,
+    });
+    assert!(image.to_bytes().is_err());
+    image.symbols[0].name.pop();
+    let bytes = image.to_bytes().unwrap();
+    assert_eq!(ProgramImage::from_bytes(&bytes).unwrap(), image);
+}
+
+#[test]
+fn validate_checks_the_layout() {
+    let image = asm::assemble_image(SRC).unwrap();
+    assert!(image.validate(64).is_ok());
+    let fits = image.validate(3).unwrap_err().to_string();
+    assert!(fits.contains("data section 3..4 doesn't fit"));
+
+    let overlapping = ProgramImage::new(0, section(0, 4), section(2, 4));
+    let overlap = overlapping.validate(64).unwrap_err().to_string();
+    assert!(overlap.contains("overlap"));
+
+    let outside = ProgramImage::new(4, section(0, 4), Section::default());
+    let entry = outside.validate(64).unwrap_err().to_string();
+    assert!(entry.contains("entry point 4 is outside"));
+
+    let empty = ProgramImage::new(0, Section::default(), Section::default());
+    assert!(empty.validate(64).is_err());
+}
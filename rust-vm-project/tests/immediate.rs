@@ -0,0 +1,47 @@
+use rust_vm_project::{asm, disasm, FlatMemory, CPU};
+
+fn run(src: &str) -> CPU {
+    let words = asm::assemble(src).unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(64))
+        .program(&words, 0)
+        .build()
+        .unwrap();
+    cpu.run().unwrap();
+    cpu
+}
+
+#[test]
+fn li_zero_extends_and_lis_sign_extends() {
+    let cpu = run("
+            li r0 4294967295
+            lis r1 -1
+            lis r2 2147483647
+            lis r3 -2147483648
+            li r4 end
+    end:    ext
+    ");
+    assert_eq!(cpu.registers()[0], u32::MAX as u64);
+    assert_eq!(cpu.registers()[1], u64::MAX);
+    assert_eq!(cpu.registers()[2], i32::MAX as u64);
+    assert_eq!(cpu.registers()[3], i32::MIN as i64 as u64);
+    assert_eq!(cpu.registers()[4], 5);
+}
+
+#[test]
+fn immediates_out_of_range_are_rejected() {
+    assert!(asm::assemble("li r0 4294967296").is_err());
+    assert!(asm::assemble("li r0 -1").is_err());
+    assert!(asm::assemble("lis r0 2147483648").is_err());
+    assert!(asm::assemble("lis r0 -2147483649").is_err());
+    assert!(asm::assemble("lis r0 18446744073709551615").is_err());
+}
+
+#[test]
+fn immediates_disassemble_the_way_they_were_written() {
+    let words = asm::assemble("li r3 0x10\nlis r7 -42").unwrap();
+    let text: Vec<String> = disasm::disassemble(&words, 0)
+        .into_iter()
+        .map(|line| line.text)
+        .collect();
+    assert_eq!(text, ["li r3 16", "lis r7 -42"]);
+}
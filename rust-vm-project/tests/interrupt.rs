@@ -0,0 +1,134 @@
+use rust_vm_project::device::{Timer, TIMER};
+use rust_vm_project::interrupt::IRQ_VECTOR;
+use rust_vm_project::{asm, FlatMemory, VmFault, CPU};
+
+/// Words for a vector table with `handler` in `slot` and nothing elsewhere
+fn vectors(slot: u32, handler: &str) -> String {
+    let mut table = String::from("vectors:");
+    for i in 0..IRQ_VECTOR + 16 {
+        let word = if i == slot { handler } else { "0" };
+        table.push_str(&format!("\n    .word {}", word));
+    }
+    table
+}
+
+#[test]
+fn timer_preempts_a_busy_loop() {
+    // the main loop spins until the tick handler has run three times
+    let src = format!(
+        "
+            lod period r0
+            wrt r0 {timer}
+            lod three r1
+            ei
+    spin:   cmp r2 r1
+            bne spin
+            ext
+    tick:   icrr r2
+            iret
+            .data
+    period: .word 5
+    three:  .word 3
+    {table}
+        ",
+        timer = TIMER,
+        table = vectors(IRQ_VECTOR, "tick")
+    );
+    let image = asm::assemble_image(&src).unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(256))
+        .image(&image)
+        .device(TIMER, 2, Box::new(Timer::new(0)))
+        .build()
+        .unwrap();
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.registers()[2], 3);
+    assert!(cpu.interrupts().enabled());
+    assert_eq!(cpu.stack_pointer(), cpu.stack_region().end);
+}
+
+#[test]
+fn fault_handler_resumes_after_the_faulting_instruction() {
+    let src = format!(
+        "
+            icrr r0
+            div r0 r1 r2
+            icrr r3
+            ext
+    oops:   icrr r4
+            iret
+            .data
+    {}
+        ",
+        vectors(VmFault::DivideByZero.vector(), "oops")
+    );
+    let image = asm::assemble_image(&src).unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(256))
+        .image(&image)
+        .build()
+        .unwrap();
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.registers()[2], 0);
+    assert_eq!(cpu.registers()[3], 1);
+    assert_eq!(cpu.registers()[4], 1);
+}
+
+#[test]
+fn faults_without_a_handler_reach_the_host() {
+    let src = format!(
+        "
+            div r0 r1 r2
+            ext
+            .data
+    {}
+        ",
+        vectors(VmFault::ArithmeticOverflow.vector(), "0")
+    );
+    let image = asm::assemble_image(&src).unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(256))
+        .image(&image)
+        .build()
+        .unwrap();
+
+    let fault = cpu.run().unwrap_err();
+    assert_eq!(fault.kind, VmFault::DivideByZero);
+    assert_eq!(fault.pc, 0);
+}
+
+#[test]
+fn iret_restores_flags_and_lines_wait_while_disabled() {
+    let src = format!(
+        "
+            icrr r0
+            cmp r0 r1
+            ei
+            clr r0
+            ext
+    irq:    cmp r1 r1
+            iret
+            .data
+    {}
+        ",
+        vectors(IRQ_VECTOR + 2, "irq")
+    );
+    let image = asm::assemble_image(&src).unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(256))
+        .image(&image)
+        .build()
+        .unwrap();
+    cpu.interrupts_mut().raise(2);
+
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    assert_eq!(cpu.interrupts().pending(), 1 << 2);
+    let flags = cpu.flags();
+    cpu.step().unwrap(); // ei
+    cpu.step().unwrap(); // the handler's cmp
+    assert!(cpu.flags().zero);
+    cpu.step().unwrap(); // iret
+    assert_eq!(cpu.flags(), flags);
+    assert_eq!(cpu.program_counter(), 3);
+    assert_eq!(cpu.interrupts().pending(), 0);
+    cpu.run().unwrap();
+}
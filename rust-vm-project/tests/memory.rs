@@ -0,0 +1,77 @@
+use rust_vm_project::{asm, FlatMemory, MemoryBackend, PagedMemory, VmFault, CPU, PAGE_WORDS};
+
+const FAR: &str = "
+            lod 3735879680 r1
+            icrr r1
+            wrt r1 4000000000
+            push r1
+            ext
+";
+
+#[test]
+fn paged_memory_reaches_the_whole_address_space() {
+    let words = asm::assemble(FAR).unwrap();
+    let mut cpu = CPU::builder(PagedMemory::new())
+        .program(&words, 0)
+        .program(&[41], 0xdead_0000)
+        .build()
+        .unwrap();
+    assert_eq!(cpu.stack_region(), u32::MAX - 64..u32::MAX);
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.registers()[1], 42);
+    assert_eq!(cpu.memory().read(4_000_000_000).unwrap(), 42);
+    assert_eq!(cpu.memory().read(u32::MAX - 1).unwrap(), 42);
+    // code, the far word, the store and the stack
+    assert_eq!(cpu.memory().pages(), 4);
+}
+
+#[test]
+fn paged_memory_allocates_on_nonzero_writes() {
+    let mut memory = PagedMemory::new();
+    assert_eq!(memory.read(0x1234_5678).unwrap(), 0);
+    memory.write(0x1234_5678, 0).unwrap();
+    assert_eq!(memory.pages(), 0);
+
+    memory.write(PAGE_WORDS as u32 * 3 + 1, 9).unwrap();
+    memory.write(5, 7).unwrap();
+    assert_eq!(memory.pages(), 2);
+    let chunks = memory.chunks();
+    assert_eq!(chunks[0].0, 0);
+    assert_eq!(chunks[1].0, PAGE_WORDS as u32 * 3);
+    assert_eq!(chunks[1].1[1], 9);
+
+    memory.clear();
+    assert_eq!(memory.pages(), 0);
+    assert_eq!(memory.read(5).unwrap(), 0);
+}
+
+#[test]
+fn flat_memory_ends_where_it_says() {
+    let memory = FlatMemory::new(16);
+    assert_eq!(memory.words(), 16);
+    assert_eq!(memory.read(16), Err(VmFault::MemoryOutOfRange(16)));
+}
+
+#[test]
+fn paged_snapshots_round_trip() {
+    let words = asm::assemble(FAR).unwrap();
+    let mut cpu = CPU::builder(PagedMemory::new())
+        .program(&words, 0)
+        .program(&[41], 0xdead_0000)
+        .build()
+        .unwrap();
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    let snapshot = cpu.snapshot();
+    cpu.run().unwrap();
+
+    let mut resumed = CPU::builder(PagedMemory::new()).build().unwrap();
+    resumed.restore(&snapshot).unwrap();
+    resumed.run().unwrap();
+    assert_eq!(resumed.registers(), cpu.registers());
+    assert_eq!(resumed.memory(), cpu.memory());
+
+    let mut flat = CPU::builder(FlatMemory::new(64)).build().unwrap();
+    assert!(flat.restore(&snapshot).is_err());
+}
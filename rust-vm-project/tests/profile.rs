@@ -0,0 +1,70 @@
+use rust_vm_project::profile::Profile;
+use rust_vm_project::{asm, FlatMemory, Instruction, CPU};
+
+const FACT: &str = "
+    start:  lod n r0
+            call fact
+            ext
+    fact:   clr r2
+            cmp r0 r2
+            bne recurse
+            clr r1
+            icrr r1
+            ret
+    recurse:
+            push r0
+            lod one r3
+            sub r0 r3 r0
+            call fact
+            pop r0
+            mul r0 r1 r1
+            ret
+            .data
+    n:      .word 3
+    one:    .word 1
+";
+
+fn profile() -> (Profile, Vec<rust_vm_project::image::Symbol>) {
+    let image = asm::assemble_image(FACT).unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(128))
+        .image(&image)
+        .build()
+        .unwrap();
+    let mut profile = Profile::new();
+    cpu.run_profiled(&mut profile).unwrap();
+    assert_eq!(cpu.registers()[1], 6);
+    (profile, image.symbols)
+}
+
+#[test]
+fn counts_per_pc_opcode_and_branch() {
+    let (profile, _) = profile();
+    // main, the test in each of 4 calls, the base case and 3 recursive cases
+    assert_eq!(profile.total(), 3 + 4 * 3 + 3 + 3 * 7);
+    assert_eq!(profile.pc(3).unwrap().count, 4);
+    let branch = profile.pc(5).unwrap();
+    assert_eq!((branch.taken, branch.not_taken), (3, 1));
+    assert_eq!(profile.opcode_count(Instruction::Return), 4);
+    assert_eq!(profile.hot_spots()[0].1.count, 4);
+}
+
+#[test]
+fn report_and_folded_stacks() {
+    let (profile, symbols) = profile();
+    let report = profile.report(&symbols, 5);
+    // targets are printed as addresses, the label heads its own line
+    assert!(report
+        .lines()
+        .any(|line| line == "         4  10.26      5  bne 9  taken 3 not 1"));
+
+    let folded = profile.folded(&symbols);
+    assert!(folded.lines().any(|line| line == "start 3"));
+    assert!(folded
+        .lines()
+        .any(|line| line == "start;fact;fact;fact;fact 6"));
+    let total: u64 = folded
+        .lines()
+        .map(|line| line.rsplit(' ').next().unwrap().parse::<u64>().unwrap())
+        .sum();
+    assert_eq!(total, profile.total());
+}
@@ -0,0 +1,88 @@
+use rust_vm_project::interrupt::VECTORS;
+use rust_vm_project::{asm, Access, FlatMemory, Permissions, VmFault, CPU};
+
+fn machine(src: &str) -> CPU {
+    let image = asm::assemble_image(src).unwrap();
+    CPU::builder(FlatMemory::new(128))
+        .image(&image)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn images_cannot_overwrite_their_code() {
+    let mut cpu = machine(
+        "
+            icrr r0
+            wrt r0 0
+            ext
+        ",
+    );
+    let code = cpu.memory().data()[0];
+    let fault = cpu.run().unwrap_err();
+    assert_eq!(fault.kind, VmFault::Protection(0, Access::Write));
+    assert_eq!(fault.pc, 1);
+    assert_eq!(cpu.memory().data()[0], code);
+}
+
+#[test]
+fn images_cannot_execute_data() {
+    let mut cpu = machine(
+        "
+            spc stuff
+            .data
+    stuff:  .word 0
+        ",
+    );
+    let fault = cpu.run().unwrap_err();
+    assert_eq!(fault.kind, VmFault::Protection(1, Access::Execute));
+    assert_eq!(fault.pc, 1);
+
+    // nor anything past the sections, the stack included
+    let mut cpu = machine("spc 100");
+    let fault = cpu.run().unwrap_err();
+    assert_eq!(fault.kind, VmFault::Protection(100, Access::Execute));
+}
+
+#[test]
+fn regions_shadow_older_ones_and_the_default() {
+    let words = asm::assemble("lod 40 r0\nlod 50 r1\nwrt r1 60\next").unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(128))
+        .program(&words, 0)
+        .protect(40, 20, Permissions::NONE)
+        .protect(40, 5, Permissions::R)
+        .default_permissions(Permissions::RX)
+        .build()
+        .unwrap();
+    let mc = cpu.memory_controller();
+    assert_eq!(mc.permissions(42), Permissions::R);
+    assert_eq!(mc.permissions(59), Permissions::NONE);
+    assert_eq!(mc.permissions(60).to_string(), "r-x");
+
+    assert_eq!(
+        cpu.run().unwrap_err().kind,
+        VmFault::Protection(50, Access::Read)
+    );
+    assert_eq!(cpu.program_counter(), 1);
+}
+
+#[test]
+fn protection_faults_can_be_handled() {
+    let mut src = String::from(
+        "
+            icrr r0
+            wrt r0 fix
+            icrr r1
+            ext
+    fix:    icrr r2
+            iret
+            .data
+    vectors:",
+    );
+    for i in 0..VECTORS {
+        src.push_str(if i == 7 { "\n .word fix" } else { "\n .word 0" });
+    }
+    let mut cpu = machine(&src);
+    cpu.run().unwrap();
+    assert_eq!(&cpu.registers()[..3], &[1, 1, 1]);
+}
@@ -0,0 +1,95 @@
+use rust_vm_project::device::{HaltPort, Timer, HALT, TIMER};
+use rust_vm_project::{asm, FlatMemory, CPU};
+
+const SUM: &str = "
+            lod n r1
+    loop:   icrr r0
+            add r0 r2 r2
+            push r2
+            pop r3
+            cmp r0 r1
+            bne loop
+            wrt r2 total
+            ext
+    n:      .word 100
+    total:  .word 0
+";
+
+fn machine() -> CPU {
+    let words = asm::assemble(SUM).unwrap();
+    CPU::builder(FlatMemory::new(256))
+        .program(&words, 0)
+        .device(TIMER, 2, Box::new(Timer::new(0)))
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn resuming_a_snapshot_finishes_the_same_way() {
+    let mut cpu = machine();
+    cpu.memory_controller_mut().write(TIMER, 7).unwrap();
+    cpu.run_with_budget(150).unwrap();
+    let snapshot = cpu.snapshot();
+    cpu.run().unwrap();
+
+    let mut resumed = machine();
+    resumed.restore(&snapshot).unwrap();
+    assert_eq!(resumed.memory_controller().peek(TIMER).unwrap(), 7);
+    assert_eq!(resumed.cycles(), 150);
+    resumed.run().unwrap();
+
+    assert_eq!(resumed.registers(), cpu.registers());
+    assert_eq!(resumed.memory().data(), cpu.memory().data());
+    assert_eq!(resumed.memory().data()[10], 5050);
+    assert_eq!(
+        resumed.memory_controller().peek(TIMER + 1).unwrap(),
+        cpu.memory_controller().peek(TIMER + 1).unwrap()
+    );
+}
+
+#[test]
+fn bad_snapshots_leave_the_machine_alone() {
+    let mut cpu = machine();
+    cpu.run_with_budget(20).unwrap();
+    let snapshot = cpu.snapshot();
+
+    let mut other = machine();
+    assert!(other.restore(&snapshot[..snapshot.len() - 1]).is_err());
+    assert!(other.restore(b"nope").is_err());
+    assert_eq!(other.program_counter(), 0);
+    assert_eq!(other.registers(), &[0; 8]);
+
+    let mut smaller = CPU::builder(FlatMemory::new(128)).build().unwrap();
+    assert!(smaller.restore(&snapshot).is_err());
+    let mut no_timer = CPU::builder(FlatMemory::new(256)).build().unwrap();
+    assert!(no_timer.restore(&snapshot).is_err());
+}
+
+#[test]
+fn corrupt_device_state_is_refused_before_anything_changes() {
+    let machine = || {
+        let words = asm::assemble(SUM).unwrap();
+        CPU::builder(FlatMemory::new(256))
+            .program(&words, 0)
+            .device(TIMER, 2, Box::new(Timer::new(0)))
+            .device(HALT, 1, Box::new(HaltPort::default()))
+            .build()
+            .unwrap()
+    };
+    let mut cpu = machine();
+    cpu.memory_controller_mut().write(TIMER, 7).unwrap();
+    cpu.memory_controller_mut().write(HALT, 3).unwrap();
+    cpu.step().unwrap();
+    let mut snapshot = cpu.snapshot();
+    // the timer's state comes last, a running timer with no cycles left
+    let len = snapshot.len();
+    snapshot[len - 8..].fill(0);
+
+    let mut other = machine();
+    let err = other.restore(&snapshot).unwrap_err();
+    assert!(err.to_string().contains("no cycles remaining"));
+    assert_eq!(other.memory_controller().peek(HALT).unwrap(), 0);
+    assert_eq!(other.memory_controller().peek(TIMER).unwrap(), 0);
+    assert_eq!(other.program_counter(), 0);
+    other.step().unwrap();
+}
@@ -0,0 +1,78 @@
+use rust_vm_project::{asm, FlatMemory, VmFault, CPU};
+
+fn run(src: &str) -> Result<CPU, VmFault> {
+    let words = asm::assemble(src).unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(128))
+        .program(&words, 0)
+        .build()?;
+    cpu.run().map_err(|fault| fault.kind)?;
+    Ok(cpu)
+}
+
+#[test]
+fn push_pop_is_last_in_first_out() {
+    let cpu = run("
+            icrr r0
+            push r0
+            icrr r0
+            push r0
+            pop r1
+            pop r2
+            ext
+        ")
+    .unwrap();
+    assert_eq!(cpu.registers()[1], 2);
+    assert_eq!(cpu.registers()[2], 1);
+    assert_eq!(cpu.stack_pointer(), cpu.stack_region().end);
+}
+
+#[test]
+fn recursive_factorial() {
+    // r0 = n, result in r1
+    let cpu = run("
+            lod n r0
+            call fact
+            ext
+    fact:   clr r2
+            cmp r0 r2
+            bne recurse
+            clr r1
+            icrr r1
+            ret
+    recurse:
+            push r0
+            lod one r3
+            sub r0 r3 r0
+            call fact
+            pop r0
+            mul r0 r1 r1
+            ret
+    one:    .word 1
+    n:      .word 10
+        ")
+    .unwrap();
+    assert_eq!(cpu.registers()[1], 3628800);
+}
+
+#[test]
+fn overflow_and_underflow_fault() {
+    assert!(matches!(
+        run("loop: call loop"),
+        Err(VmFault::StackOverflow(_))
+    ));
+    assert!(matches!(run("pop r0"), Err(VmFault::StackUnderflow(_))));
+    assert!(matches!(run("ret"), Err(VmFault::StackUnderflow(_))));
+}
+
+#[test]
+fn jump_to_itself_does_not_fall_through() {
+    let words = asm::assemble("here: spc here\next").unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(16))
+        .program(&words, 0)
+        .build()
+        .unwrap();
+    for _ in 0..10 {
+        assert!(cpu.step().unwrap());
+        assert_eq!(cpu.program_counter(), 0);
+    }
+}
@@ -0,0 +1,74 @@
+use rust_vm_project::trace::{self, TraceFilter, TraceFormat, TraceRecord, Tracer};
+use rust_vm_project::{asm, FlatMemory, CPU};
+
+fn trace(src: &str, format: TraceFormat) -> Vec<u8> {
+    let words = asm::assemble(src).unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(64))
+        .program(&words, 0)
+        .build()
+        .unwrap();
+    let mut tracer = Tracer::new(Vec::new(), format).unwrap();
+    cpu.run_traced(&mut tracer).unwrap();
+    tracer.into_inner().unwrap()
+}
+
+const COUNT: &str = "
+            lod three r1
+    loop:   icrr r0
+            wrt r0 total
+            cmp r0 r1
+            bne loop
+            ext
+    three:  .word 3
+    total:  .word 0
+";
+
+#[test]
+fn records_register_changes_and_writes() {
+    let records = trace::read_trace(&trace(COUNT, TraceFormat::Json)).unwrap();
+    assert_eq!(records.len(), 1 + 4 * 3 + 1);
+    assert_eq!(
+        records[2],
+        TraceRecord {
+            cycle: 2,
+            pc: 2,
+            raw: asm::assemble("wrt r0 7").unwrap()[0],
+            text: "wrt r0 7".to_string(),
+            registers: vec![],
+            writes: vec![(7, 1)],
+        }
+    );
+    assert_eq!(records[1].registers, vec![(0, 1)]);
+}
+
+#[test]
+fn binary_and_json_read_back_the_same() {
+    let json = trace::read_trace(&trace(COUNT, TraceFormat::Json)).unwrap();
+    let binary = trace(COUNT, TraceFormat::Binary);
+    assert!(binary.starts_with(&trace::MAGIC));
+    assert_eq!(trace::read_trace(&binary).unwrap(), json);
+}
+
+#[test]
+fn filter_and_diff() {
+    let records = trace::read_trace(&trace(COUNT, TraceFormat::Json)).unwrap();
+    let filter = TraceFilter {
+        addr: Some(7),
+        ..TraceFilter::default()
+    };
+    let writes: Vec<_> = records.iter().filter(|r| filter.matches(r)).collect();
+    assert_eq!(writes.len(), 3);
+
+    assert_eq!(trace::diverge(&records, &records), None);
+    let other = trace::read_trace(&trace(
+        &COUNT.replace("three:  .word 3", "three:  .word 2"),
+        TraceFormat::Binary,
+    ))
+    .unwrap();
+    let divergence = trace::diverge(&records, &other).unwrap();
+    assert_eq!(divergence.index, 0);
+    let shorter = &records[..5];
+    let divergence = trace::diverge(&records, shorter).unwrap();
+    assert_eq!(divergence.index, 5);
+    assert_eq!(divergence.right, None);
+}
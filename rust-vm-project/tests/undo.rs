@@ -0,0 +1,106 @@
+use rust_vm_project::debugger::{Debugger, Stop};
+use rust_vm_project::undo::UndoLog;
+use rust_vm_project::{asm, FlatMemory, CPU};
+
+const COUNT: &str = "
+            lod five r1
+    loop:   icrr r0
+            push r0
+            wrt r0 last
+            cmp r0 r1
+            bne loop
+            clr r2
+            ext
+    five:   .word 5
+    last:   .word 0
+";
+
+fn machine() -> CPU {
+    let words = asm::assemble(COUNT).unwrap();
+    CPU::builder(FlatMemory::new(128))
+        .program(&words, 0)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn stepping_all_the_way_back_restores_the_start() {
+    let mut cpu = machine();
+    let start = cpu.snapshot();
+    let mut log = UndoLog::default();
+    while cpu.step_recorded(&mut log).unwrap() {}
+    assert_eq!(cpu.memory().data()[9], 5);
+
+    let steps = log.len();
+    for _ in 0..steps {
+        assert!(cpu.step_back(&mut log));
+    }
+    assert!(!cpu.step_back(&mut log));
+    assert_eq!(cpu.snapshot(), start);
+}
+
+#[test]
+fn the_limit_drops_the_oldest_history() {
+    let mut cpu = machine();
+    let mut log = UndoLog::new(1000);
+    while cpu.step_recorded(&mut log).unwrap() {}
+    assert!(log.bytes() <= 1000);
+    assert!(log.len() < 1 + 5 * 5 + 2);
+
+    let kept = log.len();
+    for _ in 0..kept {
+        cpu.step_back(&mut log);
+    }
+    assert!(log.is_empty());
+    assert_ne!(cpu.program_counter(), 0);
+}
+
+#[test]
+fn reverse_continue_and_last_write() {
+    let mut debugger = Debugger::new(machine());
+    debugger.add_breakpoint(3);
+    assert_eq!(debugger.cont(), Stop::Breakpoint(3));
+    assert_eq!(debugger.cont(), Stop::Breakpoint(3));
+    assert_eq!(debugger.cont(), Stop::Breakpoint(3));
+    assert_eq!(debugger.cpu().registers()[0], 3);
+
+    assert_eq!(debugger.reverse_continue(), Stop::Breakpoint(3));
+    assert_eq!(debugger.cpu().registers()[0], 2);
+    assert_eq!(debugger.cpu().memory().data()[9], 1);
+
+    // r0 was last bumped by the icrr at 1
+    assert_eq!(debugger.back_to_write(0), Stop::Stepped);
+    assert_eq!(debugger.cpu().program_counter(), 1);
+    assert_eq!(debugger.cpu().registers()[0], 1);
+    assert_eq!(debugger.back_to_write(1), Stop::Stepped);
+    assert_eq!(debugger.cpu().program_counter(), 0);
+    assert_eq!(debugger.step_back_n(1), Stop::HistoryStart);
+
+    debugger.remove_breakpoint(3);
+    assert_eq!(debugger.cont(), Stop::Exited);
+    assert_eq!(debugger.step_back_n(3), Stop::Stepped);
+    assert!(!debugger.exited());
+    assert_eq!(debugger.cont(), Stop::Exited);
+}
+
+#[test]
+fn last_write_counts_writes_of_the_same_value() {
+    let words = asm::assemble("li r1 7\nicrr r2\nli r1 7\nclra\next").unwrap();
+    let cpu = CPU::builder(FlatMemory::new(64))
+        .program(&words, 0)
+        .build()
+        .unwrap();
+    let mut debugger = Debugger::new(cpu);
+    assert_eq!(debugger.cont(), Stop::Exited);
+
+    // clra writes every register, r3 included though it was already zero
+    assert_eq!(debugger.back_to_write(3), Stop::Stepped);
+    assert_eq!(debugger.cpu().program_counter(), 3);
+    // the second li stored the 7 r1 already held
+    assert_eq!(debugger.back_to_write(1), Stop::Stepped);
+    assert_eq!(debugger.cpu().program_counter(), 2);
+    assert_eq!(debugger.cpu().registers()[1], 7);
+    assert_eq!(debugger.back_to_write(1), Stop::Stepped);
+    assert_eq!(debugger.cpu().program_counter(), 0);
+    assert_eq!(debugger.cpu().registers()[1], 0);
+}
@@ -0,0 +1,126 @@
+use rust_vm_project::asm;
+use rust_vm_project::image::{ProgramImage, Section};
+use rust_vm_project::verify::verify;
+use rust_vm_project::{FlatMemory, Instruction, MemoryBackend, CPU};
+
+/// Addresses of everything `verify` flags in `src`
+fn flagged(src: &str, memory_words: u64) -> Vec<u32> {
+    let image = asm::assemble_image(src).unwrap();
+    verify(&image, memory_words)
+        .iter()
+        .map(|diagnostic| diagnostic.addr)
+        .collect()
+}
+
+#[test]
+fn sound_programs_pass() {
+    let src = "
+    start:  li r1 5
+    loop:   cmp r1 r0
+            beq done
+            call dec
+            icrr r3
+            spc loop
+    dec:    li r2 1
+            sub r1 r2 r1
+            ret
+    done:   wrt r3 out
+            ext
+            .data
+    out:    .word 0
+    ";
+    assert_eq!(flagged(src, 64), Vec::<u32>::new());
+
+    let image = asm::assemble_image(src).unwrap();
+    let mut cpu = CPU::builder(FlatMemory::new(64))
+        .image(&image)
+        .build()
+        .unwrap();
+    cpu.run().unwrap();
+    let out = image.symbol("out").unwrap();
+    assert_eq!(cpu.memory().read(out).unwrap(), 5);
+}
+
+#[test]
+fn bad_words_are_flagged() {
+    let bad_register = Instruction::IncrementReg as u64 | 9 << 8;
+    let src = format!(
+        "
+            .word 255
+            .word {}
+            ext
+        ",
+        bad_register
+    );
+    let image = asm::assemble_image(&src).unwrap();
+    let diagnostics = verify(&image, 64);
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0].addr, 0);
+    assert_eq!(diagnostics[0].message, "unknown opcode 255");
+    assert_eq!(diagnostics[1].addr, 1);
+    assert_eq!(diagnostics[1].to_string(), "1: `icrr` names register r9");
+}
+
+#[test]
+fn memory_operands_stay_in_memory() {
+    let src = "
+            lod 64 r1
+            lod 63 r1
+            wrt r1 0
+            ext
+    ";
+    assert_eq!(flagged(src, 64), vec![0, 2]);
+}
+
+#[test]
+fn jumps_stay_in_the_code() {
+    let src = "
+            spc 100
+            ieqe r1 r2 5
+            call data
+            beq 3
+            ext
+            .data
+    data:   .word 0
+    ";
+    assert_eq!(flagged(src, 64), vec![0, 1, 2]);
+}
+
+#[test]
+fn falling_off_the_end_is_flagged() {
+    assert_eq!(flagged("li r1 1\nicrr r1", 64), vec![1]);
+    assert_eq!(flagged("fix: icrr r1\nspc fix", 64), Vec::<u32>::new());
+}
+
+#[test]
+fn entry_and_vectors_point_into_the_code() {
+    let src = "
+    handler: iret
+    start:   ext
+             .data
+    vectors: .word 0
+             .word handler
+             .word 40
+    ";
+    // the table starts right after the two code words
+    assert_eq!(flagged(src, 64), vec![4]);
+
+    let mut image = asm::assemble_image(src).unwrap();
+    image.entry = 3;
+    let diagnostics = verify(&image, 64);
+    assert_eq!(diagnostics[0].addr, 3);
+    assert!(diagnostics[0].message.contains("entry point"));
+}
+
+#[test]
+fn code_past_the_address_space_is_flagged() {
+    let code = Section {
+        base: u32::MAX,
+        words: vec![Instruction::Exit as u64; 2],
+    };
+    let image = ProgramImage::new(u32::MAX, code, Section::default());
+    let diagnostics = verify(&image, 1 << 32);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].addr, u32::MAX);
+    assert!(diagnostics[0].message.contains("doesn't fit"));
+}